@@ -1,5 +1,41 @@
 use anchor_lang::prelude::*; // Anchor framework for Solana smart contracts
-use anchor_spl::token::{ self, Token, Transfer, TokenAccount, Mint }; // SPL token utilities
+use anchor_lang::system_program::{ self, Transfer }; // Native SOL transfers
+use anchor_spl::associated_token::{ get_associated_token_address_with_program_id, AssociatedToken }; // Associated token account utilities
+use anchor_spl::token_interface::{ self, Mint, TokenAccount, TokenInterface, TransferChecked, CloseAccount, HarvestWithheldTokensToMint }; // SPL Token and Token-2022 utilities
+use anchor_spl::token_interface::spl_token_2022::{ self, extension::{ transfer_fee::TransferFeeAmount, BaseStateWithExtensions, StateWithExtensions } }; // Token-2022 transfer fee extension
+
+/// Seed prefix for escrow account PDAs
+pub const ESCROW_SEED: &[u8] = b"escrow";
+
+/// Seed prefix for the vault token account holding escrowed tokens
+pub const VAULT_SEED: &[u8] = b"vault";
+
+/// Seed of the singleton program config PDA
+pub const CONFIG_SEED: &[u8] = b"config";
+
+/// Seed prefix for the per-mint treasury token accounts collecting protocol fees
+pub const TREASURY_SEED: &[u8] = b"treasury";
+
+/// Seed prefix for the per-mint rule PDAs set by the admin or guardian
+pub const MINT_RULE_SEED: &[u8] = b"mint_rule";
+
+/// Maximum number of milestones an escrow can be split into
+pub const MAX_MILESTONES: usize = 8;
+
+/// Maximum number of approvers an escrow can require approvals from
+pub const MAX_APPROVERS: usize = 8;
+
+/// Maximum number of mints a single escrow basket can hold, including the primary mint
+pub const MAX_BASKET_MINTS: usize = 4;
+
+/// Maximum number of recipients an escrow can be split between
+pub const MAX_SPLIT_RECIPIENTS: usize = 8;
+
+/// Basis points representing 100% when splitting an escrow
+pub const BPS_DENOMINATOR: u16 = 10_000;
+
+/// Highest protocol fee the admin may set, in basis points (10%)
+pub const MAX_FEE_BPS: u16 = 1_000;
 
 // Unique program ID for this Solana program
 declare_id!("FQsrCdTzAVkqg6eTximoptrxpMERQ5A2uZ6VjcBnGWo9");
@@ -13,122 +49,3240 @@ pub mod escrow_solana {
     ///
     /// # Arguments
     /// - `ctx`: Context containing accounts and instruction data
-    /// - `amount`: The number of tokens to lock in escrow
-    /// - `expiry`: The time (in seconds) after which the escrow can be withdrawn
+    /// - `args`: Parameters of the escrow (see `CreateEscrowArgs`)
     ///
     /// # Returns
     /// - `Ok(())` if the escrow creation succeeds
-    pub fn create_escrow(ctx: Context<CreateEscrow>, amount: u64, expiry: i64) -> Result<()> {
+    pub fn create_escrow<'info>(
+        ctx: Context<'_, '_, '_, 'info, CreateEscrow<'info>>,
+        args: CreateEscrowArgs
+    ) -> Result<()> {
+        let CreateEscrowArgs {
+            escrow_id,
+            amount,
+            release_after,
+            refund_after,
+            cancel_policy,
+            milestones,
+            vesting,
+            arbiter,
+            approvers,
+            approval_threshold,
+            nft,
+            basket,
+            splits,
+        } = args;
+
+        // Ensure no new funds enter the program while it or the mint is paused, and
+        // that the mint may be escrowed under the deployment's mint policy
+        let mint_rule = ensure_can_fund(&ctx.accounts.config, &ctx.accounts.mint_rule)?;
+
+        require!(amount > 0, EscrowError::InvalidAmount);
+
+        // Ensure an NFT escrow holds the single token of a non-divisible, one-of-one mint
+        if nft {
+            let mint = &ctx.accounts.mint;
+            require!(
+                mint.decimals == 0 && mint.supply == 1 && amount == 1,
+                EscrowError::InvalidNft
+            );
+            require!(milestones.is_empty() && vesting.is_none(), EscrowError::InvalidNft);
+        }
+
+        // Ensure the milestones, if any, are non-zero and add up to the escrowed amount
+        require!(milestones.len() <= MAX_MILESTONES, EscrowError::TooManyMilestones);
+        if !milestones.is_empty() {
+            let mut total: u64 = 0;
+            for milestone in &milestones {
+                require!(*milestone > 0, EscrowError::InvalidAmount);
+                total = total.checked_add(*milestone).ok_or(EscrowError::InvalidMilestones)?;
+            }
+            require!(total == amount, EscrowError::InvalidMilestones);
+        }
+
+        // Ensure the arbiter, if any, is independent of both parties
+        if let Some(arbiter) = arbiter {
+            require!(
+                arbiter != ctx.accounts.depositor.key() && arbiter != ctx.accounts.recipient.key(),
+                EscrowError::InvalidArbiter
+            );
+        }
+
+        // Ensure the approvers, if any, are unique and the threshold is reachable
+        require!(approvers.len() <= MAX_APPROVERS, EscrowError::InvalidApprovers);
+        for (index, approver) in approvers.iter().enumerate() {
+            require!(!approvers[..index].contains(approver), EscrowError::InvalidApprovers);
+        }
+        require!(
+            (approval_threshold as usize) <= approvers.len() &&
+                (approvers.is_empty() || approval_threshold > 0),
+            EscrowError::InvalidApprovalThreshold
+        );
+
+        // Ensure the basket legs, if any, fit and come with their accounts; baskets are
+        // released and refunded in full, so they cannot be staged, vested or split
+        require!(basket.len() < MAX_BASKET_MINTS, EscrowError::InvalidBasket);
+        require!(
+            basket.is_empty() || (milestones.is_empty() && vesting.is_none() && arbiter.is_none()),
+            EscrowError::InvalidBasket
+        );
+        require!(ctx.remaining_accounts.len() == basket.len() * 4, EscrowError::InvalidBasket);
+
+        // Ensure the split, if any, starts with the recipient, lists each party once and
+        // adds up to 100%; split escrows are paid out in full, so they cannot be staged,
+        // vested or hold a basket
+        require!(splits.len() <= MAX_SPLIT_RECIPIENTS, EscrowError::InvalidSplits);
+        if !splits.is_empty() {
+            require_keys_eq!(
+                splits[0].recipient,
+                ctx.accounts.recipient.key(),
+                EscrowError::InvalidSplits
+            );
+            let mut total: u16 = 0;
+            for (index, split) in splits.iter().enumerate() {
+                require!(split.bps > 0, EscrowError::InvalidSplits);
+                require!(
+                    splits[..index].iter().all(|other| other.recipient != split.recipient),
+                    EscrowError::InvalidSplits
+                );
+                total = total.checked_add(split.bps).ok_or(EscrowError::InvalidSplits)?;
+            }
+            require!(total == BPS_DENOMINATOR, EscrowError::InvalidSplits);
+            require!(
+                milestones.is_empty() && vesting.is_none() && basket.is_empty(),
+                EscrowError::InvalidSplits
+            );
+        }
+
+        // Resolve both time conditions against the current clock
+        let clock = Clock::get()?;
+
+        // Ensure a vesting schedule, if any, is well-formed and not combined with milestones
+        if let Some(schedule) = &vesting {
+            require!(milestones.is_empty(), EscrowError::InvalidVestingSchedule);
+            schedule.validate(&clock, &ctx.accounts.config)?;
+        }
+        let (time_basis, release_after, refund_after) = resolve_time_window(
+            release_after,
+            refund_after,
+            &clock,
+            &ctx.accounts.config
+        )?;
+
+        // The depositor can never refund a vesting escrow (see Escrow::ensure_can_cancel),
+        // so its cancel policy and refund deadline must not promise otherwise
+        if let Some(schedule) = &vesting {
+            require!(
+                cancel_policy == CancelPolicy::Never &&
+                    time_basis == (TimeBasis::UnixTimestamp as u8) &&
+                    refund_after >= schedule.end,
+                EscrowError::InvalidVestingSchedule
+            );
+        }
+
+        // Move the escrowed tokens from the depositor into the program-owned vault
+        transfer_tokens(
+            ctx.accounts.depositor_token_account.to_account_info(),
+            ctx.accounts.mint.to_account_info(),
+            ctx.accounts.mint.decimals,
+            ctx.accounts.vault.to_account_info(),
+            ctx.accounts.depositor.to_account_info(),
+            &ctx.accounts.token_program,
+            amount
+        )?;
+
+        // Token-2022 mints may withhold a transfer fee, so escrow what actually arrived
+        ctx.accounts.vault.reload()?;
+
+        // Milestone amounts are fixed up front, so they must have arrived in full
+        require!(
+            milestones.is_empty() || ctx.accounts.vault.amount == amount,
+            EscrowError::InvalidMilestones
+        );
+
+        // The NFT itself must have arrived in the vault
+        require!(!nft || ctx.accounts.vault.amount == 1, EscrowError::InvalidNft);
+
+        // Move every extra basket leg into its own escrow-owned vault
+        let mut legs: Vec<BasketLeg> = Vec::with_capacity(basket.len());
+        for (amount, accounts) in basket.into_iter().zip(ctx.remaining_accounts.chunks(4)) {
+            let leg = deposit_basket_leg(
+                accounts,
+                amount,
+                &ctx.accounts.depositor,
+                ctx.accounts.escrow.key(),
+                &ctx.accounts.config,
+                &ctx.accounts.token_program
+            )?;
+            require!(
+                leg.mint != ctx.accounts.mint.key() && legs.iter().all(|other| other.mint != leg.mint),
+                EscrowError::InvalidBasket
+            );
+            legs.push(leg);
+        }
+
         let escrow = &mut ctx.accounts.escrow;
         escrow.depositor = ctx.accounts.depositor.key(); // Set depositor's public key
         escrow.recipient = ctx.accounts.recipient.key(); // Set recipient's public key
-        escrow.amount = amount; // Set the amount of tokens for the escrow
-        escrow.expiry = Clock::get()?.unix_timestamp + expiry; // Calculate the escrow expiration time
-        escrow.status = EscrowStatus::Pending as u8; // Set the initial status to Pending
+        escrow.mint = ctx.accounts.mint.key(); // Set the mint of the escrowed token
+        escrow.amount = ctx.accounts.vault.amount; // Set the amount of tokens for the escrow
+        escrow.release_after = release_after; // Set the earliest release time
+        escrow.refund_after = refund_after; // Set the refund deadline
+        escrow.time_basis = time_basis; // Set whether the times are timestamps or slots
+        escrow.kind = EscrowKind::Transfer as u8; // One-way transfer to the recipient
+        escrow.status = if approvers.is_empty() {
+            EscrowStatus::Pending as u8 // Set the initial status to Pending
+        } else {
+            EscrowStatus::AwaitingApprovals as u8 // Releases wait for enough approvals
+        };
+        escrow.escrow_id = escrow_id; // Store the id used in the PDA seeds
+        escrow.bump = ctx.bumps.escrow; // Store the bump for signing as the escrow PDA
+        escrow.cancel_policy = cancel_policy as u8; // Set when the depositor may cancel
+        escrow.fee_bps = ctx.accounts.config.fee_bps; // Lock in the current fee rate
+        escrow.fee_min = mint_rule.map_or(0, |rule| rule.fee_min); // Lock in the mint's minimum fee
+        escrow.amount_released = 0; // Nothing has been paid out yet
+        escrow.milestones = milestones
+            .into_iter()
+            .map(|amount| Milestone { amount, released: false })
+            .collect(); // Set the tranches released by release_milestone
+        escrow.vesting = vesting; // Set the schedule claimed through claim_vested
+        escrow.arbiter = arbiter; // Set the optional arbiter resolving disputes
+        escrow.approvers = approvers; // Set who may approve the release
+        escrow.approval_threshold = approval_threshold; // Set how many approvals are required
+        escrow.approvals = 0; // Nobody has approved yet
+        escrow.basket = legs; // Set the extra legs moved together with the primary mint
+        escrow.splits = splits; // Set how the release is shared between recipients
+
+        let timestamp = clock.unix_timestamp;
+        emit!(EscrowCreated {
+            escrow: escrow.key(),
+            depositor: escrow.depositor,
+            recipient: escrow.recipient,
+            mint: escrow.mint,
+            kind: escrow.kind,
+            amount: escrow.amount,
+            release_after,
+            refund_after,
+            timestamp,
+        });
+        emit!(EscrowFunded {
+            escrow: escrow.key(),
+            depositor: escrow.depositor,
+            mint: escrow.mint,
+            amount: escrow.amount,
+            timestamp,
+        });
+        for leg in &escrow.basket {
+            emit!(EscrowFunded {
+                escrow: escrow.key(),
+                depositor: escrow.depositor,
+                mint: leg.mint,
+                amount: leg.amount,
+                timestamp,
+            });
+        }
         Ok(())
     }
 
-    /// Withdraws tokens from an escrow account to the recipient
+    /// Withdraws all tokens remaining in the escrow vault to the recipient
     ///
     /// # Arguments
     /// - `ctx`: Context containing accounts and instruction data
     ///
     /// # Returns
     /// - `Ok(())` if the withdrawal succeeds
-    pub fn withdraw_escrow(ctx: Context<WithdrawEscrow>) -> Result<()> {
+    pub fn withdraw_escrow<'info>(ctx: Context<'_, '_, '_, 'info, WithdrawEscrow<'info>>) -> Result<()> {
         let escrow = &mut ctx.accounts.escrow;
 
         // Ensure escrow status is valid for withdrawal
         require!(escrow.status == (EscrowStatus::Pending as u8), EscrowError::InvalidStatus);
 
-        // Ensure the escrow has expired before allowing withdrawal
-        require!(Clock::get()?.unix_timestamp >= escrow.expiry, EscrowError::EscrowExpired);
+        // Swap offers are settled through take_offer
+        require!(escrow.kind == (EscrowKind::Transfer as u8), EscrowError::InvalidEscrowKind);
 
-        // Transfer tokens from the depositor's account to the recipient's account
-        let cpi_accounts = Transfer {
-            from: ctx.accounts.depositor_token_account.to_account_info(),
-            to: ctx.accounts.recipient_token_account.to_account_info(),
-            authority: ctx.accounts.depositor.to_account_info(),
-        };
-        let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
-        token::transfer(cpi_ctx, escrow.amount)?;
+        // Ensure the escrow has unlocked before allowing withdrawal
+        require!(escrow.now()? >= escrow.release_after, EscrowError::ReleaseTimeNotReached);
+
+        // Take the protocol fee from the vault into the treasury
+        let fee = collect_fee(
+            escrow,
+            &ctx.accounts.vault,
+            &ctx.accounts.mint,
+            ctx.accounts.treasury.to_account_info(),
+            &ctx.accounts.token_program,
+            ctx.accounts.vault.amount
+        )?;
+
+        // Transfer the remaining tokens from the vault to the recipient's account
+        let amount = ctx.accounts.vault.amount - fee;
+        pay_recipients(
+            escrow,
+            &ctx.accounts.vault,
+            &ctx.accounts.mint,
+            ctx.accounts.recipient_token_account.to_account_info(),
+            ctx.remaining_accounts,
+            &ctx.accounts.token_program,
+            amount
+        )?;
+
+        // Close the emptied vault; the escrow account itself is closed by Anchor
+        close_vault(
+            escrow,
+            &ctx.accounts.vault,
+            &ctx.accounts.mint,
+            ctx.accounts.depositor.to_account_info(),
+            &ctx.accounts.token_program
+        )?;
+
+        // Pay out every extra basket leg to the recipient in the same transaction
+        settle_basket(
+            escrow,
+            ctx.remaining_accounts,
+            escrow.recipient,
+            ctx.accounts.depositor.to_account_info(),
+            &ctx.accounts.token_program,
+            true
+        )?;
 
         escrow.status = EscrowStatus::Completed as u8; // Update the escrow status to Completed
+        emit!(EscrowReleased {
+            escrow: escrow.key(),
+            recipient: escrow.recipient,
+            mint: escrow.mint,
+            amount,
+            fee,
+            completed: true,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
         Ok(())
     }
-}
 
-/// The Escrow account stores data about an escrow instance
-#[account]
-pub struct Escrow {
-    pub depositor: Pubkey, // Public key of the depositor
-    pub recipient: Pubkey, // Public key of the recipient
-    pub amount: u64, // Amount of tokens in escrow
-    pub expiry: i64, // Expiry time (timestamp)
-    pub status: u8, // Status of the escrow (e.g., Pending, Completed)
-}
+    /// Lets the recipient claim the escrowed tokens once the escrow has unlocked,
+    /// without needing the depositor to sign
+    ///
+    /// # Arguments
+    /// - `ctx`: Context containing accounts and instruction data
+    ///
+    /// # Returns
+    /// - `Ok(())` if the claim succeeds
+    pub fn claim<'info>(ctx: Context<'_, '_, '_, 'info, Claim<'info>>) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
 
-impl Escrow {
-    /// Total space required for the Escrow account
-    /// Includes 8 bytes for the account discriminator plus fields
-    pub const LEN: usize = 8 + 32 + 32 + 8 + 8 + 1;
-}
+        // Ensure escrow status is valid for claiming
+        require!(escrow.status == (EscrowStatus::Pending as u8), EscrowError::InvalidStatus);
 
-/// Represents the status of an escrow
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
-#[repr(u8)]
-pub enum EscrowStatus {
-    Pending = 0, // Escrow is awaiting withdrawal
-    Completed = 1, // Escrow has been successfully withdrawn
-}
+        // Swap offers are settled through take_offer
+        require!(escrow.kind == (EscrowKind::Transfer as u8), EscrowError::InvalidEscrowKind);
 
-/// Accounts required for the `create_escrow` instruction
-#[derive(Accounts)]
-pub struct CreateEscrow<'info> {
-    /// The escrow account being initialized
-    #[account(init, payer = depositor, space = Escrow::LEN + 8)]
-    pub escrow: Account<'info, Escrow>,
+        // Vesting escrows are claimed gradually through claim_vested
+        require!(escrow.vesting.is_none(), EscrowError::VestingEscrow);
 
-    /// The depositor of the escrow (payer of account rent)
-    #[account(mut)]
-    pub depositor: Signer<'info>,
+        // Milestone escrows are paid out by the depositor through release_milestone
+        require!(escrow.milestones.is_empty(), EscrowError::MilestoneEscrow);
 
-    /// The recipient account that will receive tokens
-    pub recipient: Account<'info, TokenAccount>,
+        // Ensure the escrow has unlocked before allowing the claim
+        require!(escrow.now()? >= escrow.release_after, EscrowError::ReleaseTimeNotReached);
 
-    /// Token program ID (must match the SPL token program)
-    #[account(address = token::ID)]
-    pub token_program: Program<'info, Token>,
+        // Take the protocol fee from the vault into the treasury
+        let fee = collect_fee(
+            escrow,
+            &ctx.accounts.vault,
+            &ctx.accounts.mint,
+            ctx.accounts.treasury.to_account_info(),
+            &ctx.accounts.token_program,
+            ctx.accounts.vault.amount
+        )?;
 
-    /// System program (required for account creation)
-    pub system_program: Program<'info, System>,
+        // Transfer the remaining tokens from the vault to the recipient's account
+        let amount = ctx.accounts.vault.amount - fee;
+        pay_recipients(
+            escrow,
+            &ctx.accounts.vault,
+            &ctx.accounts.mint,
+            ctx.accounts.recipient_token_account.to_account_info(),
+            ctx.remaining_accounts,
+            &ctx.accounts.token_program,
+            amount
+        )?;
 
-    /// Rent system variable (to check account rent exemption)
-    pub rent: Sysvar<'info, Rent>,
-}
+        // Close the emptied vault; the escrow account itself is closed by Anchor
+        close_vault(
+            escrow,
+            &ctx.accounts.vault,
+            &ctx.accounts.mint,
+            ctx.accounts.depositor.to_account_info(),
+            &ctx.accounts.token_program
+        )?;
 
-/// Accounts required for the `withdraw_escrow` instruction
-#[derive(Accounts)]
-pub struct WithdrawEscrow<'info> {
-    /// The escrow account being accessed
-    #[account(mut)]
-    pub escrow: Account<'info, Escrow>,
+        // Pay out every extra basket leg to the recipient in the same transaction
+        settle_basket(
+            escrow,
+            ctx.remaining_accounts,
+            escrow.recipient,
+            ctx.accounts.depositor.to_account_info(),
+            &ctx.accounts.token_program,
+            true
+        )?;
 
-    /// The depositor of the escrow (must sign the transaction)
-    #[account(mut)]
-    pub depositor: Signer<'info>,
+        escrow.status = EscrowStatus::Completed as u8; // Update the escrow status to Completed
+        emit!(EscrowReleased {
+            escrow: escrow.key(),
+            recipient: escrow.recipient,
+            mint: escrow.mint,
+            amount,
+            fee,
+            completed: true,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
 
-    /// Token account of the depositor
-    #[account(mut)]
-    pub depositor_token_account: Account<'info, TokenAccount>,
+    /// Releases a single milestone of the escrow to the recipient
+    ///
+    /// The escrow is completed, and its accounts closed, once every milestone
+    /// has been paid out.
+    ///
+    /// # Arguments
+    /// - `ctx`: Context containing accounts and instruction data
+    /// - `index`: Index of the milestone to release
+    ///
+    /// # Returns
+    /// - `Ok(())` if the release succeeds
+    pub fn release_milestone(ctx: Context<ReleaseMilestone>, index: u8) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
 
-    /// Token account of the recipient
-    #[account(mut)]
-    pub recipient_token_account: Account<'info, TokenAccount>,
+        // Ensure escrow status is valid for releasing
+        require!(escrow.status == (EscrowStatus::Pending as u8), EscrowError::InvalidStatus);
+
+        // Ensure the milestone exists and has not been paid out yet
+        let milestone = escrow.milestones
+            .get(index as usize)
+            .ok_or(EscrowError::InvalidMilestone)?
+            .clone();
+        require!(!milestone.released, EscrowError::MilestoneAlreadyReleased);
+
+        escrow.milestones[index as usize].released = true; // Mark the milestone as paid
+        escrow.amount_released = escrow.amount_released
+            .checked_add(milestone.amount)
+            .ok_or(EscrowError::InvalidMilestones)?; // Track the total paid out
+
+        // The last milestone empties the vault, including any tokens sent to it
+        // after creation, so that the vault can be closed
+        let completed = escrow.milestones.iter().all(|milestone| milestone.released);
+        let payout = if completed { ctx.accounts.vault.amount } else { milestone.amount };
+
+        // Take the protocol fee from the vault into the treasury
+        let fee = collect_fee(
+            escrow,
+            &ctx.accounts.vault,
+            &ctx.accounts.mint,
+            ctx.accounts.treasury.to_account_info(),
+            &ctx.accounts.token_program,
+            payout
+        )?;
+
+        // Transfer the rest of the payout from the vault to the recipient's account
+        transfer_from_vault(
+            escrow,
+            &ctx.accounts.vault,
+            &ctx.accounts.mint,
+            ctx.accounts.recipient_token_account.to_account_info(),
+            &ctx.accounts.token_program,
+            payout - fee
+        )?;
+
+        // Complete the escrow and reclaim its rent once every milestone is paid
+        if completed {
+            close_vault(
+                escrow,
+                &ctx.accounts.vault,
+                &ctx.accounts.mint,
+                ctx.accounts.depositor.to_account_info(),
+                &ctx.accounts.token_program
+            )?;
+            escrow.status = EscrowStatus::Completed as u8; // Update the escrow status to Completed
+            escrow.close(ctx.accounts.depositor.to_account_info())?;
+        }
+
+        emit!(EscrowReleased {
+            escrow: escrow.key(),
+            recipient: escrow.recipient,
+            mint: escrow.mint,
+            amount: payout - fee,
+            fee,
+            completed: escrow.status == (EscrowStatus::Completed as u8),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    /// Lets the recipient claim the portion of a vesting escrow that has vested
+    /// but not been claimed yet
+    ///
+    /// The escrow is completed, and its accounts closed, once the full amount
+    /// has been claimed.
+    ///
+    /// # Arguments
+    /// - `ctx`: Context containing accounts and instruction data
+    ///
+    /// # Returns
+    /// - `Ok(())` if the claim succeeds
+    pub fn claim_vested(ctx: Context<ClaimVested>) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+
+        // Ensure escrow status is valid for claiming
+        require!(escrow.status == (EscrowStatus::Pending as u8), EscrowError::InvalidStatus);
+
+        // Work out how much has vested beyond what was already claimed
+        let schedule = escrow.vesting.clone().ok_or(EscrowError::NotVestingEscrow)?;
+        let vested = schedule.vested_amount(escrow.amount, Clock::get()?.unix_timestamp)?;
+        let claimable = vested.saturating_sub(escrow.amount_released);
+        require!(claimable > 0, EscrowError::NothingToClaim);
+
+        // The completing claim empties the vault, including any tokens sent to it
+        // after creation, so that the vault can be closed
+        let completed = vested == escrow.amount;
+        let payout = if completed { ctx.accounts.vault.amount } else { claimable };
+
+        // Take the protocol fee from the vault into the treasury
+        let fee = collect_fee(
+            escrow,
+            &ctx.accounts.vault,
+            &ctx.accounts.mint,
+            ctx.accounts.treasury.to_account_info(),
+            &ctx.accounts.token_program,
+            payout
+        )?;
+
+        // Transfer the rest of the payout from the vault to the recipient's account
+        transfer_from_vault(
+            escrow,
+            &ctx.accounts.vault,
+            &ctx.accounts.mint,
+            ctx.accounts.recipient_token_account.to_account_info(),
+            &ctx.accounts.token_program,
+            payout - fee
+        )?;
+        escrow.amount_released = vested; // Track the total paid out
+
+        // Complete the escrow and reclaim its rent once everything is claimed
+        if completed {
+            close_vault(
+                escrow,
+                &ctx.accounts.vault,
+                &ctx.accounts.mint,
+                ctx.accounts.depositor.to_account_info(),
+                &ctx.accounts.token_program
+            )?;
+            escrow.status = EscrowStatus::Completed as u8; // Update the escrow status to Completed
+            escrow.close(ctx.accounts.depositor.to_account_info())?;
+        }
+
+        emit!(EscrowReleased {
+            escrow: escrow.key(),
+            recipient: escrow.recipient,
+            mint: escrow.mint,
+            amount: payout - fee,
+            fee,
+            completed: escrow.status == (EscrowStatus::Completed as u8),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    /// Cancels a pending escrow and refunds the vault balance to the depositor
+    ///
+    /// The recipient may always refund voluntarily; the depositor may cancel
+    /// once the refund deadline has passed, or earlier as allowed by the
+    /// escrow's `CancelPolicy`.
+    ///
+    /// # Arguments
+    /// - `ctx`: Context containing accounts and instruction data
+    ///
+    /// # Returns
+    /// - `Ok(())` if the cancellation succeeds
+    pub fn cancel_escrow<'info>(ctx: Context<'_, '_, '_, 'info, CancelEscrow<'info>>) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+
+        // Ensure escrow status is valid for cancellation
+        require!(escrow.is_open(), EscrowError::InvalidStatus);
+
+        // Ensure the signer is allowed to cancel at this time
+        escrow.ensure_can_cancel(ctx.accounts.authority.key())?;
+
+        // Return the vault balance to the depositor's account
+        let amount = ctx.accounts.vault.amount;
+        transfer_from_vault(
+            escrow,
+            &ctx.accounts.vault,
+            &ctx.accounts.mint,
+            ctx.accounts.depositor_token_account.to_account_info(),
+            &ctx.accounts.token_program,
+            amount
+        )?;
+
+        // Close the emptied vault; the escrow account itself is closed by Anchor
+        close_vault(
+            escrow,
+            &ctx.accounts.vault,
+            &ctx.accounts.mint,
+            ctx.accounts.depositor.to_account_info(),
+            &ctx.accounts.token_program
+        )?;
+
+        // Refund every extra basket leg to the depositor in the same transaction
+        settle_basket(
+            escrow,
+            ctx.remaining_accounts,
+            escrow.depositor,
+            ctx.accounts.depositor.to_account_info(),
+            &ctx.accounts.token_program,
+            false
+        )?;
+
+        escrow.status = EscrowStatus::Cancelled as u8; // Update the escrow status to Cancelled
+        emit!(EscrowCancelled {
+            escrow: escrow.key(),
+            depositor: escrow.depositor,
+            mint: escrow.mint,
+            amount,
+            cancelled_by: ctx.accounts.authority.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    /// Records an approver's approval of the release; once the approval
+    /// threshold is reached the escrow becomes releasable
+    ///
+    /// # Arguments
+    /// - `ctx`: Context containing accounts and instruction data
+    ///
+    /// # Returns
+    /// - `Ok(())` if the approval is recorded
+    pub fn approve_release(ctx: Context<ApproveRelease>) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+
+        // Ensure escrow status is valid for approvals
+        require!(
+            escrow.status == (EscrowStatus::AwaitingApprovals as u8),
+            EscrowError::InvalidStatus
+        );
+
+        // Ensure the signer is an approver who has not approved yet
+        let index = escrow.approvers
+            .iter()
+            .position(|approver| *approver == ctx.accounts.approver.key())
+            .ok_or(EscrowError::InvalidApprover)?;
+        let bit = 1u8 << index;
+        require!(escrow.approvals & bit == 0, EscrowError::AlreadyApproved);
+
+        escrow.approvals |= bit; // Record the approval in the bitmap
+
+        // Unlock the release once enough approvers have signed off
+        if escrow.approvals.count_ones() >= (escrow.approval_threshold as u32) {
+            escrow.status = EscrowStatus::Pending as u8; // Update the escrow status to Pending
+        }
+
+        emit!(ReleaseApproved {
+            escrow: escrow.key(),
+            approver: ctx.accounts.approver.key(),
+            approvals: escrow.approvals.count_ones() as u8,
+            approval_threshold: escrow.approval_threshold,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    /// Raises a dispute on an escrow that has an arbiter, freezing all
+    /// releases, claims and refunds until the arbiter resolves it
+    ///
+    /// # Arguments
+    /// - `ctx`: Context containing accounts and instruction data
+    ///
+    /// # Returns
+    /// - `Ok(())` if the dispute is raised
+    pub fn raise_dispute(ctx: Context<RaiseDispute>) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+        let authority = ctx.accounts.authority.key();
+
+        // Ensure escrow status is valid for a dispute
+        require!(escrow.is_open(), EscrowError::InvalidStatus);
+
+        // Only escrows with an arbiter can be disputed
+        require!(escrow.arbiter.is_some(), EscrowError::NoArbiter);
+
+        // Only the depositor or the recipient may raise a dispute
+        require!(
+            authority == escrow.depositor || authority == escrow.recipient,
+            EscrowError::Unauthorized
+        );
+
+        escrow.status = EscrowStatus::Disputed as u8; // Update the escrow status to Disputed
+        emit!(DisputeRaised {
+            escrow: escrow.key(),
+            raised_by: authority,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    /// Resolves a dispute by splitting the vault between the recipient and
+    /// the depositor, then closes the escrow
+    ///
+    /// # Arguments
+    /// - `ctx`: Context containing accounts and instruction data
+    /// - `recipient_bps`: Share of the vault paid to the recipient, in basis points
+    ///
+    /// # Returns
+    /// - `Ok(())` if the dispute is resolved
+    pub fn resolve_dispute<'info>(
+        ctx: Context<'_, '_, '_, 'info, ResolveDispute<'info>>,
+        recipient_bps: u16
+    ) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+
+        // Ensure escrow status is valid for resolution
+        require!(escrow.status == (EscrowStatus::Disputed as u8), EscrowError::InvalidStatus);
+
+        // Ensure the split is a valid share of the vault
+        require!(recipient_bps <= BPS_DENOMINATOR, EscrowError::InvalidBasisPoints);
+
+        // Work out the recipient's share, the depositor receives the rest
+        let balance = ctx.accounts.vault.amount;
+        let recipient_amount = u64::try_from(
+            ((balance as u128) * (recipient_bps as u128)) / (BPS_DENOMINATOR as u128)
+        ).map_err(|_| EscrowError::InvalidBasisPoints)?;
+        let depositor_amount = balance - recipient_amount;
+
+        // Take the protocol fee on the recipient's share from the vault into the treasury
+        let fee = collect_fee(
+            escrow,
+            &ctx.accounts.vault,
+            &ctx.accounts.mint,
+            ctx.accounts.treasury.to_account_info(),
+            &ctx.accounts.token_program,
+            recipient_amount
+        )?;
+        let recipient_amount = recipient_amount - fee;
+
+        // Transfer each party's share from the vault
+        if recipient_amount > 0 {
+            pay_recipients(
+                escrow,
+                &ctx.accounts.vault,
+                &ctx.accounts.mint,
+                ctx.accounts.recipient_token_account.to_account_info(),
+                ctx.remaining_accounts,
+                &ctx.accounts.token_program,
+                recipient_amount
+            )?;
+        }
+        if depositor_amount > 0 {
+            transfer_from_vault(
+                escrow,
+                &ctx.accounts.vault,
+                &ctx.accounts.mint,
+                ctx.accounts.depositor_token_account.to_account_info(),
+                &ctx.accounts.token_program,
+                depositor_amount
+            )?;
+        }
+
+        // Close the emptied vault; the escrow account itself is closed by Anchor
+        close_vault(
+            escrow,
+            &ctx.accounts.vault,
+            &ctx.accounts.mint,
+            ctx.accounts.depositor.to_account_info(),
+            &ctx.accounts.token_program
+        )?;
+
+        escrow.status = EscrowStatus::Completed as u8; // Update the escrow status to Completed
+        emit!(DisputeResolved {
+            escrow: escrow.key(),
+            arbiter: ctx.accounts.arbiter.key(),
+            recipient_amount,
+            depositor_amount,
+            fee,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    /// Creates a swap offer: the maker deposits token A into the vault and
+    /// asks for a fixed amount of token B in return
+    ///
+    /// The offer can be cancelled by the maker at any time through `cancel_escrow`.
+    ///
+    /// # Arguments
+    /// - `ctx`: Context containing accounts and instruction data
+    /// - `args`: Parameters of the offer (see `MakeOfferArgs`)
+    ///
+    /// # Returns
+    /// - `Ok(())` if the offer is created
+    pub fn make_offer(ctx: Context<MakeOffer>, args: MakeOfferArgs) -> Result<()> {
+        // Ensure no new funds enter the program while it or either mint is paused, and
+        // that both sides of the swap may be escrowed under the deployment's mint policy
+        let mint_rule = ensure_can_fund(&ctx.accounts.config, &ctx.accounts.mint_rule)?;
+        ensure_can_fund(&ctx.accounts.config, &ctx.accounts.wanted_mint_rule)?;
+
+        require!(args.amount > 0 && args.wanted_amount > 0, EscrowError::InvalidAmount);
+
+        // Move token A from the maker into the program-owned vault
+        transfer_tokens(
+            ctx.accounts.maker_token_account.to_account_info(),
+            ctx.accounts.mint.to_account_info(),
+            ctx.accounts.mint.decimals,
+            ctx.accounts.vault.to_account_info(),
+            ctx.accounts.maker.to_account_info(),
+            &ctx.accounts.token_program,
+            args.amount
+        )?;
+
+        // Token-2022 mints may withhold a transfer fee, so offer what actually arrived
+        ctx.accounts.vault.reload()?;
+
+        let now = Clock::get()?.unix_timestamp;
+        let escrow = &mut ctx.accounts.escrow;
+        escrow.depositor = ctx.accounts.maker.key(); // Set maker's public key
+        escrow.recipient = args.taker.unwrap_or_default(); // Set the designated taker, if any
+        escrow.mint = ctx.accounts.mint.key(); // Set the mint of the offered token
+        escrow.amount = ctx.accounts.vault.amount; // Set the amount of tokens on offer
+        escrow.release_after = now; // Offers can be taken immediately
+        escrow.refund_after = now; // Offers can be withdrawn by the maker at any time
+        escrow.time_basis = TimeBasis::UnixTimestamp as u8; // Times are unix timestamps
+        escrow.status = EscrowStatus::Pending as u8; // Set the initial status to Pending
+        escrow.escrow_id = args.escrow_id; // Store the id used in the PDA seeds
+        escrow.bump = ctx.bumps.escrow; // Store the bump for signing as the escrow PDA
+        escrow.cancel_policy = CancelPolicy::Anytime as u8; // The maker may cancel at any time
+        escrow.kind = EscrowKind::Offer as u8; // Two-sided swap offer
+        escrow.wanted_mint = ctx.accounts.wanted_mint.key(); // Set the mint asked for in return
+        escrow.wanted_amount = args.wanted_amount; // Set the amount asked for in return
+        escrow.fee_bps = ctx.accounts.config.fee_bps; // Lock in the current fee rate
+        escrow.fee_min = mint_rule.map_or(0, |rule| rule.fee_min); // Lock in the mint's minimum fee
+
+        emit!(EscrowCreated {
+            escrow: escrow.key(),
+            depositor: escrow.depositor,
+            recipient: escrow.recipient,
+            mint: escrow.mint,
+            kind: escrow.kind,
+            amount: escrow.amount,
+            release_after: now,
+            refund_after: now,
+            timestamp: now,
+        });
+        emit!(EscrowFunded {
+            escrow: escrow.key(),
+            depositor: escrow.depositor,
+            mint: escrow.mint,
+            amount: escrow.amount,
+            timestamp: now,
+        });
+        Ok(())
+    }
+
+    /// Takes all or part of a swap offer: the taker sends the wanted token B to
+    /// the maker and receives a pro-rata share of the offered token A from the
+    /// vault in the same transaction
+    ///
+    /// The offer stays open with reduced amounts until it is fully filled or
+    /// cancelled.
+    ///
+    /// # Arguments
+    /// - `ctx`: Context containing accounts and instruction data
+    /// - `fill_amount`: The number of wanted tokens (token B) the taker sends
+    ///
+    /// # Returns
+    /// - `Ok(())` if the swap succeeds
+    pub fn take_offer(ctx: Context<TakeOffer>, fill_amount: u64) -> Result<()> {
+        // Ensure no new funds enter the program while it is paused
+        require!(!ctx.accounts.config.paused, EscrowError::ProgramPaused);
+
+        let escrow = &mut ctx.accounts.escrow;
+
+        // Ensure escrow status is valid for taking
+        require!(escrow.status == (EscrowStatus::Pending as u8), EscrowError::InvalidStatus);
+        require!(escrow.kind == (EscrowKind::Offer as u8), EscrowError::InvalidEscrowKind);
+
+        // Offers with a designated taker can only be taken by them
+        require!(
+            escrow.recipient == Pubkey::default() || escrow.recipient == ctx.accounts.taker.key(),
+            EscrowError::InvalidRecipient
+        );
+
+        // Ensure the fill is within what is still wanted
+        require!(
+            fill_amount > 0 && fill_amount <= escrow.wanted_amount,
+            EscrowError::InvalidFillAmount
+        );
+
+        // Work out the pro-rata share of token A; a full fill takes whatever is left
+        let is_full_fill = fill_amount == escrow.wanted_amount;
+        let amount_out = if is_full_fill {
+            ctx.accounts.vault.amount
+        } else {
+            let amount_out = (fill_amount as u128)
+                .checked_mul(escrow.amount as u128)
+                .and_then(|product| product.checked_div(escrow.wanted_amount as u128))
+                .ok_or(EscrowError::InvalidFillAmount)?;
+            u64::try_from(amount_out).map_err(|_| error!(EscrowError::InvalidFillAmount))?
+        };
+        require!(amount_out > 0, EscrowError::InvalidFillAmount);
+
+        // Send token B from the taker to the maker
+        transfer_tokens(
+            ctx.accounts.taker_wanted_token_account.to_account_info(),
+            ctx.accounts.wanted_mint.to_account_info(),
+            ctx.accounts.wanted_mint.decimals,
+            ctx.accounts.maker_wanted_token_account.to_account_info(),
+            ctx.accounts.taker.to_account_info(),
+            &ctx.accounts.wanted_token_program,
+            fill_amount
+        )?;
+
+        // Take the protocol fee on token A from the vault into the treasury
+        let fee = collect_fee(
+            escrow,
+            &ctx.accounts.vault,
+            &ctx.accounts.mint,
+            ctx.accounts.treasury.to_account_info(),
+            &ctx.accounts.token_program,
+            amount_out
+        )?;
+
+        // Send the rest of token A from the vault to the taker
+        transfer_from_vault(
+            escrow,
+            &ctx.accounts.vault,
+            &ctx.accounts.mint,
+            ctx.accounts.taker_token_account.to_account_info(),
+            &ctx.accounts.token_program,
+            amount_out - fee
+        )?;
+
+        escrow.amount = escrow.amount.saturating_sub(amount_out); // Reduce the amount still on offer
+        escrow.wanted_amount -= fill_amount; // Reduce the amount still wanted
+
+        // Complete the offer and reclaim its rent once it is fully filled
+        if is_full_fill {
+            close_vault(
+                escrow,
+                &ctx.accounts.vault,
+                &ctx.accounts.mint,
+                ctx.accounts.maker.to_account_info(),
+                &ctx.accounts.token_program
+            )?;
+            escrow.status = EscrowStatus::Completed as u8; // Update the escrow status to Completed
+            escrow.close(ctx.accounts.maker.to_account_info())?;
+        }
+
+        emit!(OfferTaken {
+            escrow: escrow.key(),
+            taker: ctx.accounts.taker.key(),
+            amount_in: fill_amount,
+            amount_out: amount_out - fee,
+            fee,
+            completed: is_full_fill,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    /// Creates a new escrow of native SOL, held as lamports on the escrow PDA
+    ///
+    /// The escrowed lamports are tracked in `amount`, separately from the
+    /// rent-exempt minimum of the escrow account, which goes back to the
+    /// depositor when the escrow is closed.
+    ///
+    /// # Arguments
+    /// - `ctx`: Context containing accounts and instruction data
+    /// - `args`: Parameters of the escrow (see `CreateNativeEscrowArgs`)
+    ///
+    /// # Returns
+    /// - `Ok(())` if the escrow creation succeeds
+    pub fn create_native_escrow(
+        ctx: Context<CreateNativeEscrow>,
+        args: CreateNativeEscrowArgs
+    ) -> Result<()> {
+        // Ensure no new funds enter the program while it is paused
+        require!(!ctx.accounts.config.paused, EscrowError::ProgramPaused);
+
+        require!(args.amount > 0, EscrowError::InvalidAmount);
+
+        // Resolve both time conditions against the current clock
+        let clock = Clock::get()?;
+        let (time_basis, release_after, refund_after) = resolve_time_window(
+            args.release_after,
+            args.refund_after,
+            &clock,
+            &ctx.accounts.config
+        )?;
+
+        // Move the escrowed lamports from the depositor onto the escrow PDA
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.depositor.to_account_info(),
+            to: ctx.accounts.escrow.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(ctx.accounts.system_program.to_account_info(), cpi_accounts);
+        system_program::transfer(cpi_ctx, args.amount)?;
+
+        let escrow = &mut ctx.accounts.escrow;
+        escrow.depositor = ctx.accounts.depositor.key(); // Set depositor's public key
+        escrow.recipient = ctx.accounts.recipient.key(); // Set recipient's public key
+        escrow.mint = Pubkey::default(); // Native SOL has no mint
+        escrow.amount = args.amount; // Set the amount of lamports for the escrow
+        escrow.release_after = release_after; // Set the earliest release time
+        escrow.refund_after = refund_after; // Set the refund deadline
+        escrow.time_basis = time_basis; // Set whether the times are timestamps or slots
+        escrow.kind = EscrowKind::Native as u8; // One-way transfer of native SOL
+        escrow.status = EscrowStatus::Pending as u8; // Set the initial status to Pending
+        escrow.escrow_id = args.escrow_id; // Store the id used in the PDA seeds
+        escrow.bump = ctx.bumps.escrow; // Store the bump of the escrow PDA
+        escrow.cancel_policy = args.cancel_policy as u8; // Set when the depositor may cancel
+
+        emit!(EscrowCreated {
+            escrow: escrow.key(),
+            depositor: escrow.depositor,
+            recipient: escrow.recipient,
+            mint: escrow.mint,
+            kind: escrow.kind,
+            amount: escrow.amount,
+            release_after,
+            refund_after,
+            timestamp: clock.unix_timestamp,
+        });
+        emit!(EscrowFunded {
+            escrow: escrow.key(),
+            depositor: escrow.depositor,
+            mint: escrow.mint,
+            amount: escrow.amount,
+            timestamp: clock.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    /// Releases the escrowed lamports of a native SOL escrow to the recipient
+    ///
+    /// # Arguments
+    /// - `ctx`: Context containing accounts and instruction data
+    ///
+    /// # Returns
+    /// - `Ok(())` if the withdrawal succeeds
+    pub fn withdraw_native_escrow(ctx: Context<WithdrawNativeEscrow>) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+
+        // Ensure escrow status is valid for withdrawal
+        require!(escrow.status == (EscrowStatus::Pending as u8), EscrowError::InvalidStatus);
+        require!(escrow.kind == (EscrowKind::Native as u8), EscrowError::InvalidEscrowKind);
+
+        // Ensure the escrow has unlocked before allowing withdrawal
+        require!(escrow.now()? >= escrow.release_after, EscrowError::ReleaseTimeNotReached);
+
+        // Pay the escrowed lamports to the recipient; the rent is returned to the depositor by Anchor
+        transfer_lamports(
+            &escrow.to_account_info(),
+            &ctx.accounts.recipient.to_account_info(),
+            escrow.amount
+        )?;
+
+        escrow.status = EscrowStatus::Completed as u8; // Update the escrow status to Completed
+        emit!(EscrowReleased {
+            escrow: escrow.key(),
+            recipient: escrow.recipient,
+            mint: escrow.mint,
+            amount: escrow.amount,
+            fee: 0,
+            completed: true,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    /// Cancels a native SOL escrow and refunds the escrowed lamports, together
+    /// with the account rent, to the depositor
+    ///
+    /// Follows the same rules as `cancel_escrow`.
+    ///
+    /// # Arguments
+    /// - `ctx`: Context containing accounts and instruction data
+    ///
+    /// # Returns
+    /// - `Ok(())` if the cancellation succeeds
+    pub fn cancel_native_escrow(ctx: Context<CancelNativeEscrow>) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+
+        // Ensure escrow status is valid for cancellation
+        require!(escrow.status == (EscrowStatus::Pending as u8), EscrowError::InvalidStatus);
+        require!(escrow.kind == (EscrowKind::Native as u8), EscrowError::InvalidEscrowKind);
+
+        // Ensure the signer is allowed to cancel at this time
+        escrow.ensure_can_cancel(ctx.accounts.authority.key())?;
+
+        // The escrowed lamports and the rent are returned when Anchor closes the account
+        escrow.status = EscrowStatus::Cancelled as u8; // Update the escrow status to Cancelled
+        emit!(EscrowCancelled {
+            escrow: escrow.key(),
+            depositor: escrow.depositor,
+            mint: escrow.mint,
+            amount: escrow.amount,
+            cancelled_by: ctx.accounts.authority.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    /// Creates the program config, governing the whole deployment
+    ///
+    /// Only the program's upgrade authority may initialize the config; it
+    /// becomes the first admin.
+    ///
+    /// # Arguments
+    /// - `ctx`: Context containing accounts and instruction data
+    /// - `args`: Initial settings (see `ConfigArgs`)
+    ///
+    /// # Returns
+    /// - `Ok(())` if the config is created
+    pub fn initialize_config(ctx: Context<InitializeConfig>, args: ConfigArgs) -> Result<()> {
+        args.validate()?;
+
+        let config = &mut ctx.accounts.config;
+        config.admin = ctx.accounts.authority.key(); // The upgrade authority becomes the admin
+        config.pending_admin = None; // No admin transfer in progress
+        config.bump = ctx.bumps.config; // Store the bump of the config PDA
+        config.apply(args);
+        Ok(())
+    }
+
+    /// Updates the settings of the program config
+    ///
+    /// # Arguments
+    /// - `ctx`: Context containing accounts and instruction data
+    /// - `args`: New settings (see `ConfigArgs`)
+    ///
+    /// # Returns
+    /// - `Ok(())` if the config is updated
+    pub fn update_config(ctx: Context<UpdateConfig>, args: ConfigArgs) -> Result<()> {
+        args.validate()?;
+        ctx.accounts.config.apply(args);
+        Ok(())
+    }
+
+    /// Starts handing the admin role over to `new_admin`, who must accept it
+    /// through `accept_admin`
+    ///
+    /// # Arguments
+    /// - `ctx`: Context containing accounts and instruction data
+    /// - `new_admin`: The proposed admin
+    ///
+    /// # Returns
+    /// - `Ok(())` if the transfer is proposed
+    pub fn transfer_admin(ctx: Context<UpdateConfig>, new_admin: Pubkey) -> Result<()> {
+        ctx.accounts.config.pending_admin = Some(new_admin); // Propose the new admin
+        Ok(())
+    }
+
+    /// Completes an admin transfer; must be signed by the proposed admin
+    ///
+    /// # Arguments
+    /// - `ctx`: Context containing accounts and instruction data
+    ///
+    /// # Returns
+    /// - `Ok(())` if the new admin takes over
+    pub fn accept_admin(ctx: Context<AcceptAdmin>) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+
+        // Ensure the signer is the proposed admin
+        require!(
+            config.pending_admin == Some(ctx.accounts.new_admin.key()),
+            EscrowError::Unauthorized
+        );
+
+        config.admin = ctx.accounts.new_admin.key(); // Hand over the admin role
+        config.pending_admin = None; // The transfer is complete
+        Ok(())
+    }
+
+    /// Withdraws collected protocol fees from the treasury of a mint
+    ///
+    /// # Arguments
+    /// - `ctx`: Context containing accounts and instruction data
+    /// - `amount`: The number of tokens to withdraw
+    ///
+    /// # Returns
+    /// - `Ok(())` if the withdrawal succeeds
+    pub fn withdraw_fees(ctx: Context<WithdrawFees>, amount: u64) -> Result<()> {
+        require!(amount > 0, EscrowError::InvalidAmount);
+
+        // The treasury is owned by the config PDA, which signs the transfer
+        let seeds: &[&[u8]] = &[CONFIG_SEED, std::slice::from_ref(&ctx.accounts.config.bump)];
+        let signer_seeds: &[&[&[u8]]] = &[seeds];
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.treasury.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+            to: ctx.accounts.destination.to_account_info(),
+            authority: ctx.accounts.config.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            cpi_accounts,
+            signer_seeds
+        );
+        token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.mint.decimals)
+    }
+
+    /// Creates the treasury collecting protocol fees for a mint
+    ///
+    /// Anyone may pay for it. Releases create the treasury of the escrowed mint
+    /// themselves; basket legs need their treasuries to exist beforehand.
+    ///
+    /// # Arguments
+    /// - `ctx`: Context containing accounts and instruction data
+    ///
+    /// # Returns
+    /// - `Ok(())` if the treasury is created
+    pub fn initialize_treasury(_ctx: Context<InitializeTreasury>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Pauses or unpauses the whole program; while paused no new funds can
+    /// enter, but releases, refunds and cancellations keep working
+    ///
+    /// # Arguments
+    /// - `ctx`: Context containing accounts and instruction data
+    /// - `paused`: Whether the program is paused
+    ///
+    /// # Returns
+    /// - `Ok(())` if the flag is updated
+    pub fn set_paused(ctx: Context<SetPaused>, paused: bool) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+
+        // Ensure the signer is the admin or the guardian
+        config.ensure_admin_or_guardian(ctx.accounts.authority.key())?;
+
+        config.paused = paused; // Update the global pause flag
+        Ok(())
+    }
+
+    /// Pauses or unpauses new escrows of a single mint
+    ///
+    /// # Arguments
+    /// - `ctx`: Context containing accounts and instruction data
+    /// - `paused`: Whether the mint is paused
+    ///
+    /// # Returns
+    /// - `Ok(())` if the flag is updated
+    pub fn set_mint_paused(ctx: Context<SetMintRule>, paused: bool) -> Result<()> {
+        // Ensure the signer is the admin or the guardian
+        ctx.accounts.config.ensure_admin_or_guardian(ctx.accounts.authority.key())?;
+
+        let mint_rule = &mut ctx.accounts.mint_rule;
+        mint_rule.mint = ctx.accounts.mint.key(); // Set the mint the rule applies to
+        mint_rule.bump = ctx.bumps.mint_rule; // Store the bump of the rule PDA
+        mint_rule.paused = paused; // Update the mint's pause flag
+        Ok(())
+    }
+
+    /// Adds a mint to the allowlist or denylist, or removes it from both
+    ///
+    /// Which list is enforced depends on the config's `MintPolicy`.
+    ///
+    /// # Arguments
+    /// - `ctx`: Context containing accounts and instruction data
+    /// - `listing`: The mint's new listing
+    ///
+    /// # Returns
+    /// - `Ok(())` if the listing is updated
+    pub fn set_mint_listing(ctx: Context<SetMintRule>, listing: MintListing) -> Result<()> {
+        // Only the admin manages the lists
+        require_keys_eq!(
+            ctx.accounts.authority.key(),
+            ctx.accounts.config.admin,
+            EscrowError::Unauthorized
+        );
+
+        let mint_rule = &mut ctx.accounts.mint_rule;
+        mint_rule.mint = ctx.accounts.mint.key(); // Set the mint the rule applies to
+        mint_rule.bump = ctx.bumps.mint_rule; // Store the bump of the rule PDA
+        mint_rule.listing = listing as u8; // Update the mint's listing
+        Ok(())
+    }
+
+    /// Sets the flat minimum protocol fee of a mint, in its base units
+    ///
+    /// Applies to escrows funded afterwards; existing escrows keep the minimum
+    /// recorded when they were funded.
+    ///
+    /// # Arguments
+    /// - `ctx`: Context containing accounts and instruction data
+    /// - `fee_min`: The mint's new minimum fee
+    ///
+    /// # Returns
+    /// - `Ok(())` if the minimum fee is updated
+    pub fn set_mint_fee_min(ctx: Context<SetMintRule>, fee_min: u64) -> Result<()> {
+        // Only the admin manages fees
+        require_keys_eq!(
+            ctx.accounts.authority.key(),
+            ctx.accounts.config.admin,
+            EscrowError::Unauthorized
+        );
+
+        let mint_rule = &mut ctx.accounts.mint_rule;
+        mint_rule.mint = ctx.accounts.mint.key(); // Set the mint the rule applies to
+        mint_rule.bump = ctx.bumps.mint_rule; // Store the bump of the rule PDA
+        mint_rule.fee_min = fee_min; // Update the mint's minimum fee
+        Ok(())
+    }
+}
+
+/// Ensures new funds may enter the program: neither the program nor the
+/// escrowed mint may be paused, and the mint must pass the config's `MintPolicy`
+///
+/// Returns the mint's rule, if one was ever set.
+fn ensure_can_fund(config: &Config, mint_rule: &AccountInfo) -> Result<Option<MintRule>> {
+    require!(!config.paused, EscrowError::ProgramPaused);
+
+    let rule = MintRule::load(mint_rule)?;
+    let listing = rule.as_ref().map_or(MintListing::Unlisted as u8, |rule| rule.listing);
+    if let Some(rule) = &rule {
+        require!(!rule.paused, EscrowError::MintPaused);
+    }
+
+    let allowed = if config.mint_policy == (MintPolicy::AllowlistOnly as u8) {
+        listing == (MintListing::Allowed as u8)
+    } else if config.mint_policy == (MintPolicy::DenyListed as u8) {
+        listing != (MintListing::Denied as u8)
+    } else {
+        true
+    };
+    require!(allowed, EscrowError::MintNotAllowed);
+    Ok(rule)
+}
+
+/// Resolves the release time and refund deadline of a new escrow, returning
+/// their common time basis
+///
+/// Ensures both use the same basis, the release comes strictly before the
+/// refund deadline and the escrow does not stay locked longer than the config allows.
+fn resolve_time_window(
+    release_after: TimeCondition,
+    refund_after: TimeCondition,
+    clock: &Clock,
+    config: &Config
+) -> Result<(u8, i64, i64)> {
+    let (time_basis, release_after) = release_after.resolve(clock)?;
+    let (refund_basis, refund_after) = refund_after.resolve(clock)?;
+    require!(time_basis == refund_basis, EscrowError::MismatchedTimeConditions);
+
+    // Ensure the unlock time comes strictly before the refund deadline
+    require!(refund_after > release_after, EscrowError::InvalidTimeWindow);
+
+    // Ensure the escrow does not stay locked longer than allowed
+    let (now, max_duration) = if time_basis == (TimeBasis::Slot as u8) {
+        (TimeBasis::current_slot(clock)?, config.max_duration_slots()?)
+    } else {
+        (clock.unix_timestamp, config.max_duration)
+    };
+    let duration = refund_after.checked_sub(now).ok_or(EscrowError::TimeOverflow)?;
+    require!(duration <= max_duration, EscrowError::DurationTooLong);
+    Ok((time_basis, release_after, refund_after))
+}
+
+/// Moves `amount` lamports out of a program-owned account by adjusting balances directly
+fn transfer_lamports<'info>(
+    from: &AccountInfo<'info>,
+    to: &AccountInfo<'info>,
+    amount: u64
+) -> Result<()> {
+    let from_lamports = from.lamports().checked_sub(amount).ok_or(EscrowError::InvalidAmount)?;
+    let to_lamports = to.lamports().checked_add(amount).ok_or(EscrowError::InvalidAmount)?;
+    **from.try_borrow_mut_lamports()? = from_lamports;
+    **to.try_borrow_mut_lamports()? = to_lamports;
+    Ok(())
+}
+
+/// Transfers `amount` tokens from a token account owned by a signing `authority`
+fn transfer_tokens<'info>(
+    from: AccountInfo<'info>,
+    mint: AccountInfo<'info>,
+    decimals: u8,
+    to: AccountInfo<'info>,
+    authority: AccountInfo<'info>,
+    token_program: &Interface<'info, TokenInterface>,
+    amount: u64
+) -> Result<()> {
+    let cpi_accounts = TransferChecked {
+        from,
+        mint,
+        to,
+        authority,
+    };
+    let cpi_ctx = CpiContext::new(token_program.to_account_info(), cpi_accounts);
+    token_interface::transfer_checked(cpi_ctx, amount, decimals)
+}
+
+/// Transfers `amount` tokens out of the escrow vault, signed by the escrow PDA
+fn transfer_from_vault<'info>(
+    escrow: &Account<'info, Escrow>,
+    vault: &InterfaceAccount<'info, TokenAccount>,
+    mint: &InterfaceAccount<'info, Mint>,
+    to: AccountInfo<'info>,
+    token_program: &Interface<'info, TokenInterface>,
+    amount: u64
+) -> Result<()> {
+    transfer_signed(
+        escrow,
+        vault.to_account_info(),
+        mint.to_account_info(),
+        mint.decimals,
+        to,
+        token_program,
+        amount
+    )
+}
+
+/// Transfers `amount` tokens out of any token account owned by the escrow PDA
+fn transfer_signed<'info>(
+    escrow: &Account<'info, Escrow>,
+    from: AccountInfo<'info>,
+    mint: AccountInfo<'info>,
+    decimals: u8,
+    to: AccountInfo<'info>,
+    token_program: &Interface<'info, TokenInterface>,
+    amount: u64
+) -> Result<()> {
+    let escrow_id = escrow.escrow_id.to_le_bytes();
+    let seeds = escrow.signer_seeds(&escrow_id);
+    let signer_seeds: &[&[&[u8]]] = &[&seeds];
+    let cpi_accounts = TransferChecked {
+        from,
+        mint,
+        to,
+        authority: escrow.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        cpi_accounts,
+        signer_seeds
+    );
+    token_interface::transfer_checked(cpi_ctx, amount, decimals)
+}
+
+/// Protocol fee charged on releasing `amount` tokens: `fee_bps` of the
+/// amount, raised to `fee_min` unless that would take the whole amount
+fn protocol_fee(amount: u64, fee_bps: u16, fee_min: u64) -> Result<u64> {
+    let fee = (amount as u128)
+        .checked_mul(fee_bps as u128)
+        .and_then(|product| product.checked_div(BPS_DENOMINATOR as u128))
+        .ok_or(EscrowError::InvalidFee)?;
+    let fee = u64::try_from(fee).map_err(|_| error!(EscrowError::InvalidFee))?;
+    // The flat minimum only applies when it leaves the recipient something,
+    // so it can never swallow a small release or a whole NFT
+    if fee_min < amount {
+        Ok(fee.max(fee_min))
+    } else {
+        Ok(fee)
+    }
+}
+
+/// Moves the protocol fee on releasing `amount` tokens from the vault into
+/// the mint's treasury, returning the fee
+fn collect_fee<'info>(
+    escrow: &Account<'info, Escrow>,
+    vault: &InterfaceAccount<'info, TokenAccount>,
+    mint: &InterfaceAccount<'info, Mint>,
+    treasury: AccountInfo<'info>,
+    token_program: &Interface<'info, TokenInterface>,
+    amount: u64
+) -> Result<u64> {
+    let fee = escrow.fee_for(amount)?;
+    if fee > 0 {
+        transfer_from_vault(escrow, vault, mint, treasury, token_program, fee)?;
+    }
+    Ok(fee)
+}
+
+/// Closes the (empty) escrow vault, returning its rent to `destination`
+fn close_vault<'info>(
+    escrow: &Account<'info, Escrow>,
+    vault: &InterfaceAccount<'info, TokenAccount>,
+    mint: &InterfaceAccount<'info, Mint>,
+    destination: AccountInfo<'info>,
+    token_program: &Interface<'info, TokenInterface>
+) -> Result<()> {
+    harvest_withheld_fees(vault.to_account_info(), mint.to_account_info(), token_program)?;
+
+    let escrow_id = escrow.escrow_id.to_le_bytes();
+    let seeds = escrow.signer_seeds(&escrow_id);
+    let signer_seeds: &[&[&[u8]]] = &[&seeds];
+    let cpi_accounts = CloseAccount {
+        account: vault.to_account_info(),
+        destination,
+        authority: escrow.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        cpi_accounts,
+        signer_seeds
+    );
+    token_interface::close_account(cpi_ctx)
+}
+
+/// Moves the Token-2022 transfer fees withheld in `vault` to its mint, as a
+/// token account holding withheld fees cannot be closed
+///
+/// The harvest is permissionless but writes to the mint, so the mint must be
+/// passed as writable; it is skipped for vaults without withheld fees.
+fn harvest_withheld_fees<'info>(
+    vault: AccountInfo<'info>,
+    mint: AccountInfo<'info>,
+    token_program: &Interface<'info, TokenInterface>
+) -> Result<()> {
+    if token_program.key() != spl_token_2022::ID {
+        return Ok(());
+    }
+    let withheld = {
+        let data = vault.try_borrow_data()?;
+        let state = StateWithExtensions::<spl_token_2022::state::Account>::unpack(&data)?;
+        state
+            .get_extension::<TransferFeeAmount>()
+            .map_or(0, |fee_amount| u64::from(fee_amount.withheld_amount))
+    };
+    if withheld == 0 {
+        return Ok(());
+    }
+    let cpi_accounts = HarvestWithheldTokensToMint {
+        token_program_id: token_program.to_account_info(),
+        mint,
+    };
+    let cpi_ctx = CpiContext::new(token_program.to_account_info(), cpi_accounts);
+    token_interface::harvest_withheld_tokens_to_mint(cpi_ctx, vec![vault])
+}
+
+/// Pays `amount` tokens from the vault to the recipient, or shares it between
+/// the escrow's split recipients
+///
+/// For split escrows `remaining_accounts` holds the token accounts of every
+/// recipient after the first, in order; the first recipient is paid into
+/// `recipient_token_account` and also receives the rounding dust.
+fn pay_recipients<'info>(
+    escrow: &Account<'info, Escrow>,
+    vault: &InterfaceAccount<'info, TokenAccount>,
+    mint: &InterfaceAccount<'info, Mint>,
+    recipient_token_account: AccountInfo<'info>,
+    remaining_accounts: &[AccountInfo<'info>],
+    token_program: &Interface<'info, TokenInterface>,
+    amount: u64
+) -> Result<()> {
+    if escrow.splits.is_empty() {
+        return transfer_from_vault(escrow, vault, mint, recipient_token_account, token_program, amount);
+    }
+    require!(
+        remaining_accounts.len() == escrow.splits.len() - 1,
+        EscrowError::InvalidSplits
+    );
+
+    // Work out every recipient's share; whatever is lost to rounding goes to the first one
+    let mut shares = Vec::with_capacity(escrow.splits.len());
+    for split in &escrow.splits {
+        let share = u64::try_from(
+            ((amount as u128) * (split.bps as u128)) / (BPS_DENOMINATOR as u128)
+        ).map_err(|_| EscrowError::InvalidSplits)?;
+        shares.push(share);
+    }
+    shares[0] += amount - shares.iter().sum::<u64>();
+
+    for (index, (split, share)) in escrow.splits.iter().zip(shares).enumerate() {
+        let to = if index == 0 {
+            recipient_token_account.clone()
+        } else {
+            // Ensure the token account belongs to this recipient and holds the escrowed mint
+            let info = &remaining_accounts[index - 1];
+            let to_account = load_token_account(info, token_program.key)?;
+            require!(
+                to_account.mint == escrow.mint && to_account.owner == split.recipient,
+                EscrowError::InvalidSplits
+            );
+            info.clone()
+        };
+        if share > 0 {
+            transfer_from_vault(escrow, vault, mint, to, token_program, share)?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads a token account passed through `remaining_accounts`, owned by `token_program`
+fn load_token_account(info: &AccountInfo, token_program: &Pubkey) -> Result<TokenAccount> {
+    require_keys_eq!(*info.owner, *token_program, ErrorCode::AccountOwnedByWrongProgram);
+    TokenAccount::try_deserialize(&mut &info.try_borrow_data()?[..])
+}
+
+/// Reads a mint passed through `remaining_accounts`, owned by `token_program`
+fn load_mint(info: &AccountInfo, token_program: &Pubkey) -> Result<Mint> {
+    require_keys_eq!(*info.owner, *token_program, ErrorCode::AccountOwnedByWrongProgram);
+    Mint::try_deserialize(&mut &info.try_borrow_data()?[..])
+}
+
+/// Moves one extra basket leg from the depositor into its vault, the escrow's
+/// associated token account for the leg's mint
+///
+/// `accounts` holds the leg's mint, the depositor's token account, the vault
+/// and the leg mint's rule PDA, which is checked like the primary mint's.
+fn deposit_basket_leg<'info>(
+    accounts: &[AccountInfo<'info>],
+    amount: u64,
+    depositor: &Signer<'info>,
+    escrow: Pubkey,
+    config: &Config,
+    token_program: &Interface<'info, TokenInterface>
+) -> Result<BasketLeg> {
+    let (mint, from, vault, mint_rule) = (&accounts[0], &accounts[1], &accounts[2], &accounts[3]);
+    require!(amount > 0, EscrowError::InvalidAmount);
+
+    // Ensure the leg's mint is neither paused nor excluded by the mint policy
+    let (mint_rule_key, _) = Pubkey::find_program_address(
+        &[MINT_RULE_SEED, mint.key.as_ref()],
+        &crate::ID
+    );
+    require_keys_eq!(mint_rule.key(), mint_rule_key, EscrowError::InvalidBasket);
+    let fee_min = ensure_can_fund(config, mint_rule)?.map_or(0, |rule| rule.fee_min);
+
+    // Ensure the accounts belong to the leg's mint and the vault is owned by the escrow
+    let decimals = load_mint(mint, token_program.key)?.decimals;
+    let from_account = load_token_account(from, token_program.key)?;
+    require!(
+        from_account.mint == mint.key() && from_account.owner == depositor.key(),
+        EscrowError::InvalidBasket
+    );
+    require_keys_eq!(
+        vault.key(),
+        get_associated_token_address_with_program_id(&escrow, mint.key, token_program.key),
+        EscrowError::InvalidBasket
+    );
+
+    transfer_tokens(
+        from.clone(),
+        mint.clone(),
+        decimals,
+        vault.clone(),
+        depositor.to_account_info(),
+        token_program,
+        amount
+    )?;
+
+    // Token-2022 mints may withhold a transfer fee, so record what actually arrived
+    Ok(BasketLeg {
+        mint: mint.key(),
+        amount: load_token_account(vault, token_program.key)?.amount,
+        fee_min,
+    })
+}
+
+/// Moves the full balance of every extra basket leg to the token accounts of
+/// `to_owner` and closes the leg vaults, returning their rent to `rent_receiver`
+///
+/// `remaining_accounts` holds, for each leg in order, its mint, its vault and
+/// the receiving token account. Releases set `charge_fee` to take the protocol
+/// fee on every leg, so each chunk also holds the leg mint's treasury.
+fn settle_basket<'info>(
+    escrow: &Account<'info, Escrow>,
+    remaining_accounts: &[AccountInfo<'info>],
+    to_owner: Pubkey,
+    rent_receiver: AccountInfo<'info>,
+    token_program: &Interface<'info, TokenInterface>,
+    charge_fee: bool
+) -> Result<()> {
+    if escrow.basket.is_empty() {
+        return Ok(());
+    }
+    let chunk_len = if charge_fee { 4 } else { 3 };
+    require!(
+        remaining_accounts.len() == escrow.basket.len() * chunk_len,
+        EscrowError::InvalidBasket
+    );
+
+    let escrow_id = escrow.escrow_id.to_le_bytes();
+    let seeds = escrow.signer_seeds(&escrow_id);
+    let signer_seeds: &[&[&[u8]]] = &[&seeds];
+    let timestamp = Clock::get()?.unix_timestamp;
+
+    for (leg, accounts) in escrow.basket.iter().zip(remaining_accounts.chunks(chunk_len)) {
+        let (mint, vault, to) = (&accounts[0], &accounts[1], &accounts[2]);
+
+        // Ensure the accounts belong to this leg and the tokens go to the right party
+        require_keys_eq!(mint.key(), leg.mint, EscrowError::InvalidBasket);
+        require_keys_eq!(
+            vault.key(),
+            get_associated_token_address_with_program_id(&escrow.key(), &leg.mint, token_program.key),
+            EscrowError::InvalidBasket
+        );
+        let to_account = load_token_account(to, token_program.key)?;
+        require!(
+            to_account.mint == leg.mint && to_account.owner == to_owner,
+            EscrowError::InvalidBasket
+        );
+        let decimals = load_mint(mint, token_program.key)?.decimals;
+        let balance = load_token_account(vault, token_program.key)?.amount;
+
+        // Take the protocol fee on releases into the leg mint's treasury
+        let fee = if charge_fee {
+            let treasury = &accounts[3];
+            let (treasury_key, _) = Pubkey::find_program_address(
+                &[TREASURY_SEED, leg.mint.as_ref()],
+                &crate::ID
+            );
+            require_keys_eq!(treasury.key(), treasury_key, EscrowError::InvalidBasket);
+            let fee = protocol_fee(balance, escrow.fee_bps, leg.fee_min)?;
+            if fee > 0 {
+                transfer_signed(
+                    escrow,
+                    vault.clone(),
+                    mint.clone(),
+                    decimals,
+                    treasury.clone(),
+                    token_program,
+                    fee
+                )?;
+            }
+            fee
+        } else {
+            0
+        };
+
+        let amount = balance - fee;
+        transfer_signed(
+            escrow,
+            vault.clone(),
+            mint.clone(),
+            decimals,
+            to.clone(),
+            token_program,
+            amount
+        )?;
+
+        harvest_withheld_fees(vault.clone(), mint.clone(), token_program)?;
+        let cpi_accounts = CloseAccount {
+            account: vault.clone(),
+            destination: rent_receiver.clone(),
+            authority: escrow.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            cpi_accounts,
+            signer_seeds
+        );
+        token_interface::close_account(cpi_ctx)?;
+
+        if charge_fee {
+            emit!(EscrowReleased {
+                escrow: escrow.key(),
+                recipient: to_owner,
+                mint: leg.mint,
+                amount,
+                fee,
+                completed: true,
+                timestamp,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Singleton account holding the settings that govern the whole deployment
+#[account]
+pub struct Config {
+    pub admin: Pubkey, // Key allowed to update the config
+    pub pending_admin: Option<Pubkey>, // Proposed admin awaiting accept_admin
+    pub fee_bps: u16, // Protocol fee charged on releases of new escrows, in basis points
+    pub mint_policy: u8, // Which mints may be escrowed (see MintPolicy)
+    pub paused: bool, // Whether the program is paused
+    pub bump: u8, // Bump of the config PDA
+    pub guardian: Pubkey, // Key allowed to pause and unpause, alongside the admin
+    pub max_duration: i64, // Maximum time an escrow may stay locked, in seconds
+}
+
+impl Config {
+    /// Total space required for the Config account
+    /// Includes 8 bytes for the account discriminator plus fields
+    pub const LEN: usize = 8 + 32 + (1 + 32) + 2 + 1 + 1 + 1 + 32 + 8;
+
+    /// Applies the settings supplied to initialize_config or update_config
+    pub fn apply(&mut self, args: ConfigArgs) {
+        self.fee_bps = args.fee_bps; // Set the protocol fee rate
+        self.mint_policy = args.mint_policy as u8; // Set which mints may be escrowed
+        self.paused = args.paused; // Set whether the program is paused
+        self.guardian = args.guardian; // Set who may pause besides the admin
+        self.max_duration = args.max_duration; // Set how long escrows may stay locked
+    }
+
+    /// Maximum time an escrow may stay locked, in slots (assuming 400ms slots)
+    pub fn max_duration_slots(&self) -> Result<i64> {
+        self.max_duration
+            .checked_mul(5)
+            .map(|slots| slots / 2)
+            .ok_or(error!(EscrowError::TimeOverflow))
+    }
+
+    /// Ensures `authority` is the admin or the guardian
+    pub fn ensure_admin_or_guardian(&self, authority: Pubkey) -> Result<()> {
+        require!(
+            authority == self.admin || authority == self.guardian,
+            EscrowError::Unauthorized
+        );
+        Ok(())
+    }
+}
+
+/// Per-mint settings (pause flag, listing and minimum fee), created the first time they are needed
+#[account]
+pub struct MintRule {
+    pub mint: Pubkey, // Mint the rule applies to
+    pub paused: bool, // Whether new escrows of the mint are paused
+    pub bump: u8, // Bump of the rule PDA
+    pub listing: u8, // Whether the mint is allowlisted or denylisted (see MintListing)
+    pub fee_min: u64, // Flat minimum protocol fee, in base units of the mint
+}
+
+impl MintRule {
+    /// Total space required for the MintRule account
+    /// Includes 8 bytes for the account discriminator plus fields
+    pub const LEN: usize = 8 + 32 + 1 + 1 + 1 + 8;
+
+    /// Reads the rule at its (seed-checked) PDA, or `None` if no rule was ever set
+    pub fn load(info: &AccountInfo) -> Result<Option<MintRule>> {
+        if info.data_is_empty() {
+            return Ok(None);
+        }
+        require_keys_eq!(*info.owner, crate::ID, ErrorCode::AccountOwnedByWrongProgram);
+        Ok(Some(MintRule::try_deserialize(&mut &info.try_borrow_data()?[..])?))
+    }
+}
+
+/// The Escrow account stores data about an escrow instance
+#[account]
+pub struct Escrow {
+    pub depositor: Pubkey, // Public key of the depositor
+    pub recipient: Pubkey, // Public key of the recipient
+    pub mint: Pubkey, // Mint of the escrowed token
+    pub amount: u64, // Amount of tokens in escrow
+    pub release_after: i64, // Earliest release/claim time (timestamp or slot)
+    pub refund_after: i64, // Deadline after which the depositor can reclaim (timestamp or slot)
+    pub status: u8, // Status of the escrow (e.g., Pending, Completed)
+    pub escrow_id: u64, // Caller-supplied id used in the PDA seeds
+    pub bump: u8, // Bump of the escrow PDA
+    pub cancel_policy: u8, // When the depositor may cancel (see CancelPolicy)
+    pub time_basis: u8, // Whether release/refund times are timestamps or slots (see TimeBasis)
+    pub amount_released: u64, // Amount already paid out to the recipient
+    pub milestones: Vec<Milestone>, // Optional tranches released one by one (max MAX_MILESTONES)
+    pub vesting: Option<VestingSchedule>, // Optional linear vesting schedule
+    pub arbiter: Option<Pubkey>, // Optional third party resolving disputes
+    pub approvers: Vec<Pubkey>, // Keys whose approvals gate the release (max MAX_APPROVERS)
+    pub approval_threshold: u8, // Number of approvals required before release
+    pub approvals: u8, // Bitmap of approvers (by index) who have approved
+    pub kind: u8, // Whether this is a transfer or a swap offer (see EscrowKind)
+    pub wanted_mint: Pubkey, // Mint asked for in return (swap offers only)
+    pub wanted_amount: u64, // Amount asked for in return (swap offers only)
+    pub basket: Vec<BasketLeg>, // Extra mints held alongside the primary mint (max MAX_BASKET_MINTS - 1)
+    pub splits: Vec<RecipientShare>, // Optional shares of the release (max MAX_SPLIT_RECIPIENTS)
+    pub fee_bps: u16, // Protocol fee rate locked in when the escrow was funded
+    pub fee_min: u64, // Flat minimum fee of the mint locked in when the escrow was funded
+}
+
+impl Escrow {
+    /// Total space required for the Escrow account
+    /// Includes 8 bytes for the account discriminator plus fields
+    pub const LEN: usize =
+        8 + 32 + 32 + 32 + 8 + 8 + 8 + 1 + 8 + 1 + 1 + 1 + 8 + (4 + MAX_MILESTONES * Milestone::LEN) +
+        (1 + VestingSchedule::LEN) +
+        (1 + 32) +
+        (4 + MAX_APPROVERS * 32) +
+        1 +
+        1 +
+        1 +
+        32 +
+        8 +
+        (4 + (MAX_BASKET_MINTS - 1) * BasketLeg::LEN) +
+        (4 + MAX_SPLIT_RECIPIENTS * RecipientShare::LEN) +
+        2 +
+        8;
+
+    /// Protocol fee charged on releasing `amount` tokens of the primary mint,
+    /// under the fee terms recorded when the escrow was funded
+    pub fn fee_for(&self, amount: u64) -> Result<u64> {
+        protocol_fee(amount, self.fee_bps, self.fee_min)
+    }
+
+    /// Ensures `authority` may cancel the escrow now
+    ///
+    /// The recipient may always refund voluntarily; the depositor may cancel
+    /// once the refund deadline has passed, or earlier as allowed by the
+    /// escrow's `CancelPolicy`, except for vesting escrows, whose tokens
+    /// belong to the recipient as they vest.
+    pub fn ensure_can_cancel(&self, authority: Pubkey) -> Result<()> {
+        if authority == self.recipient {
+            return Ok(());
+        }
+
+        // Only the recipient or the depositor may cancel
+        require_keys_eq!(authority, self.depositor, EscrowError::Unauthorized);
+
+        // The depositor could otherwise take back vested tokens the recipient has not claimed yet
+        require!(self.vesting.is_none(), EscrowError::CancelNotAllowed);
+
+        // Ensure the refund deadline has passed or the cancel policy allows cancelling now
+        let now = self.now()?;
+        let allowed =
+            now >= self.refund_after ||
+            self.cancel_policy == (CancelPolicy::Anytime as u8) ||
+            (self.cancel_policy == (CancelPolicy::BeforeRelease as u8) && now < self.release_after);
+        require!(allowed, EscrowError::CancelNotAllowed);
+        Ok(())
+    }
+
+    /// Whether the escrow still holds funds that can be released or refunded
+    pub fn is_open(&self) -> bool {
+        self.status == (EscrowStatus::Pending as u8) ||
+            self.status == (EscrowStatus::AwaitingApprovals as u8)
+    }
+
+    /// Current time on the escrow's time basis (unix timestamp or slot)
+    pub fn now(&self) -> Result<i64> {
+        let clock = Clock::get()?;
+        if self.time_basis == (TimeBasis::Slot as u8) {
+            TimeBasis::current_slot(&clock)
+        } else {
+            Ok(clock.unix_timestamp)
+        }
+    }
+
+    /// Seeds of the escrow PDA, used to sign for the vault
+    pub fn signer_seeds<'a>(&'a self, escrow_id: &'a [u8; 8]) -> [&'a [u8]; 6] {
+        [
+            ESCROW_SEED,
+            self.depositor.as_ref(),
+            self.recipient.as_ref(),
+            self.mint.as_ref(),
+            escrow_id,
+            std::slice::from_ref(&self.bump),
+        ]
+    }
+}
+
+/// A tranche of an escrow released separately by the depositor
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+pub struct Milestone {
+    pub amount: u64, // Amount paid out when the milestone is released
+    pub released: bool, // Whether the milestone has been paid out
+}
+
+impl Milestone {
+    /// Space required for a serialized milestone
+    pub const LEN: usize = 8 + 1;
+}
+
+/// An extra mint held by a basket escrow in its own vault
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+pub struct BasketLeg {
+    pub mint: Pubkey, // Mint of the leg, whose vault is the escrow's associated token account
+    pub amount: u64, // Amount of tokens deposited for the leg
+    pub fee_min: u64, // Flat minimum fee of the leg's mint locked in when the escrow was funded
+}
+
+impl BasketLeg {
+    /// Space required for a serialized basket leg
+    pub const LEN: usize = 32 + 8 + 8;
+}
+
+/// A recipient's share of a split escrow, in basis points
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+pub struct RecipientShare {
+    pub recipient: Pubkey, // Owner of the token account receiving the share
+    pub bps: u16, // Share of every release, in basis points
+}
+
+impl RecipientShare {
+    /// Space required for a serialized recipient share
+    pub const LEN: usize = 32 + 2;
+}
+
+/// Linear vesting schedule, in unix timestamps
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+pub struct VestingSchedule {
+    pub start: i64, // Time vesting starts from
+    pub cliff: i64, // Nothing can be claimed before this time
+    pub end: i64, // Time the full amount has vested
+}
+
+impl VestingSchedule {
+    /// Space required for a serialized vesting schedule
+    pub const LEN: usize = 8 + 8 + 8;
+
+    /// Ensures the schedule is ordered and ends within the config's maximum escrow duration
+    pub fn validate(&self, clock: &Clock, config: &Config) -> Result<()> {
+        require!(
+            self.start < self.end && self.start <= self.cliff && self.cliff <= self.end,
+            EscrowError::InvalidVestingSchedule
+        );
+        let duration = self.end
+            .checked_sub(clock.unix_timestamp)
+            .ok_or(EscrowError::TimeOverflow)?;
+        require!(duration <= config.max_duration, EscrowError::DurationTooLong);
+        Ok(())
+    }
+
+    /// Amount of `total` vested at time `now`
+    pub fn vested_amount(&self, total: u64, now: i64) -> Result<u64> {
+        if now < self.cliff {
+            return Ok(0);
+        }
+        if now >= self.end {
+            return Ok(total);
+        }
+        let elapsed = (now - self.start) as u128;
+        let duration = (self.end - self.start) as u128;
+        let vested = (total as u128)
+            .checked_mul(elapsed)
+            .and_then(|value| value.checked_div(duration))
+            .ok_or(EscrowError::VestingOverflow)?;
+        u64::try_from(vested).map_err(|_| error!(EscrowError::VestingOverflow))
+    }
+}
+
+/// Represents the status of an escrow
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+#[repr(u8)]
+pub enum EscrowStatus {
+    Pending = 0, // Escrow is awaiting withdrawal
+    Completed = 1, // Escrow has been successfully withdrawn
+    Cancelled = 2, // Escrow has been cancelled and refunded to the depositor
+    Disputed = 3, // Escrow is frozen until the arbiter resolves the dispute
+    AwaitingApprovals = 4, // Escrow cannot be released until enough approvers sign off
+}
+
+/// Determines which mints may be escrowed through the deployment
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+#[repr(u8)]
+pub enum MintPolicy {
+    AllowAll = 0, // Any mint may be escrowed
+    AllowlistOnly = 1, // Only allowlisted mints may be escrowed
+    DenyListed = 2, // Any mint except denylisted ones may be escrowed
+}
+
+/// Whether a mint is on the allowlist or the denylist
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+#[repr(u8)]
+pub enum MintListing {
+    Unlisted = 0, // On neither list
+    Allowed = 1, // On the allowlist
+    Denied = 2, // On the denylist
+}
+
+/// Distinguishes one-way transfers from two-sided swap offers
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+#[repr(u8)]
+pub enum EscrowKind {
+    Transfer = 0, // Depositor pays the recipient
+    Offer = 1, // Maker swaps the deposited token for the wanted token
+    Native = 2, // Depositor pays the recipient in native SOL
+}
+
+/// Determines when the depositor may cancel an escrow before the refund deadline
+/// (the recipient may always refund voluntarily)
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+#[repr(u8)]
+pub enum CancelPolicy {
+    Never = 0, // Depositor can only reclaim after the refund deadline
+    BeforeRelease = 1, // Depositor can cancel only before the release time
+    Anytime = 2, // Depositor can cancel at any time while pending
+}
+
+/// Parameters supplied when creating an escrow
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct CreateEscrowArgs {
+    pub escrow_id: u64, // Caller-chosen identifier, lets a depositor hold many escrows at once
+    pub amount: u64, // The number of tokens to lock in escrow
+    pub release_after: TimeCondition, // When the escrow can be released or claimed
+    pub refund_after: TimeCondition, // When the depositor can reclaim (same basis as release_after)
+    pub cancel_policy: CancelPolicy, // When the depositor may cancel before the refund deadline
+    pub milestones: Vec<u64>, // Optional tranche amounts (summing to amount) released one by one
+    pub vesting: Option<VestingSchedule>, // Optional linear vesting schedule claimed through claim_vested
+    pub arbiter: Option<Pubkey>, // Optional third party who can resolve disputes
+    pub approvers: Vec<Pubkey>, // Optional keys whose approvals gate the release
+    pub approval_threshold: u8, // Number of approvals required (0 when there are no approvers)
+    pub nft: bool, // Whether the escrow holds a single NFT (decimals 0, supply 1, amount 1)
+    pub basket: Vec<u64>, // Optional amounts of extra mints, one per leg passed in remaining_accounts
+    pub splits: Vec<RecipientShare>, // Optional shares of the release, starting with the recipient
+}
+
+/// Settings supplied to initialize_config and update_config
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ConfigArgs {
+    pub fee_bps: u16, // Protocol fee charged on releases of new escrows, in basis points (max MAX_FEE_BPS)
+    pub mint_policy: MintPolicy, // Which mints may be escrowed
+    pub paused: bool, // Whether the program is paused
+    pub guardian: Pubkey, // Key allowed to pause and unpause, alongside the admin
+    pub max_duration: i64, // Maximum time an escrow may stay locked, in seconds
+}
+
+impl ConfigArgs {
+    /// Ensures the fee rate stays within the cap and escrows can be locked at all
+    pub fn validate(&self) -> Result<()> {
+        require!(self.fee_bps <= MAX_FEE_BPS, EscrowError::FeeTooHigh);
+        require!(self.max_duration > 0, EscrowError::InvalidTimeWindow);
+        Ok(())
+    }
+}
+
+/// Parameters supplied when making a swap offer
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct MakeOfferArgs {
+    pub escrow_id: u64, // Caller-chosen identifier, lets a maker hold many offers at once
+    pub amount: u64, // The number of offered tokens (token A) to lock in the vault
+    pub wanted_amount: u64, // The number of wanted tokens (token B) asked for in return
+    pub taker: Option<Pubkey>, // Optional designated taker; anyone can take the offer if unset
+}
+
+/// Parameters supplied when creating a native SOL escrow
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct CreateNativeEscrowArgs {
+    pub escrow_id: u64, // Caller-chosen identifier, lets a depositor hold many escrows at once
+    pub amount: u64, // The number of lamports to lock in the escrow
+    pub release_after: TimeCondition, // When the escrow unlocks for release to the recipient
+    pub refund_after: TimeCondition, // When the depositor may reclaim the lamports
+    pub cancel_policy: CancelPolicy, // When the depositor may cancel before the refund deadline
+}
+
+/// Time condition supplied when creating an escrow
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+pub enum TimeCondition {
+    RelativeSeconds(i64), // Seconds from now
+    UnixTimestamp(i64), // Absolute unix timestamp
+    Slot(u64), // Absolute slot number
+}
+
+impl TimeCondition {
+    /// Resolves the condition to an absolute time and the basis it is measured in,
+    /// rejecting times in the past
+    pub fn resolve(&self, clock: &Clock) -> Result<(u8, i64)> {
+        let (basis, now, time) = match *self {
+            TimeCondition::RelativeSeconds(seconds) => {
+                require!(seconds >= 0, EscrowError::InvalidTimeWindow);
+                let time = clock.unix_timestamp
+                    .checked_add(seconds)
+                    .ok_or(EscrowError::TimeOverflow)?;
+                (TimeBasis::UnixTimestamp, clock.unix_timestamp, time)
+            }
+            TimeCondition::UnixTimestamp(timestamp) => {
+                (TimeBasis::UnixTimestamp, clock.unix_timestamp, timestamp)
+            }
+            TimeCondition::Slot(slot) => {
+                let slot = i64::try_from(slot).map_err(|_| EscrowError::TimeOverflow)?;
+                (TimeBasis::Slot, TimeBasis::current_slot(clock)?, slot)
+            }
+        };
+        require!(time >= now, EscrowError::InvalidTimeWindow);
+        Ok((basis as u8, time))
+    }
+}
+
+/// Unit in which an escrow's release and refund times are measured
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+#[repr(u8)]
+pub enum TimeBasis {
+    UnixTimestamp = 0, // Times are unix timestamps
+    Slot = 1, // Times are slot numbers
+}
+
+impl TimeBasis {
+    /// Current slot as a signed value comparable with stored escrow times
+    pub fn current_slot(clock: &Clock) -> Result<i64> {
+        i64::try_from(clock.slot).map_err(|_| error!(EscrowError::TimeOverflow))
+    }
+}
+
+/// Accounts required for the `create_escrow` instruction
+#[derive(Accounts)]
+#[instruction(args: CreateEscrowArgs)]
+pub struct CreateEscrow<'info> {
+    /// The escrow account being initialized (PDA of depositor, recipient, mint and escrow id)
+    #[account(
+        init,
+        payer = depositor,
+        space = Escrow::LEN,
+        seeds = [
+            ESCROW_SEED,
+            depositor.key().as_ref(),
+            recipient.key().as_ref(),
+            mint.key().as_ref(),
+            &args.escrow_id.to_le_bytes(),
+        ],
+        bump,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    /// The depositor of the escrow (payer of account rent)
+    #[account(mut)]
+    pub depositor: Signer<'info>,
+
+    /// The recipient wallet that will receive tokens
+    /// CHECK: Only the key is recorded; payouts go to this wallet's associated token account
+    pub recipient: UncheckedAccount<'info>,
+
+    /// Mint of the escrowed token (SPL Token or Token-2022)
+    #[account(mint::token_program = token_program)]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Token account of the depositor the escrowed tokens are taken from
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = depositor,
+        token::token_program = token_program,
+    )]
+    pub depositor_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Vault token account holding the escrowed tokens (PDA, owned by the escrow PDA)
+    #[account(
+        init,
+        payer = depositor,
+        seeds = [VAULT_SEED, escrow.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = escrow,
+        token::token_program = token_program,
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The program config holding the pause flag and mint policy
+    #[account(seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Box<Account<'info, Config>>,
+
+    /// Rule PDA of the mint (pause flag and listing), if one was ever set
+    /// CHECK: Address checked by the seeds; read through MintRule::load as it may not exist
+    #[account(seeds = [MINT_RULE_SEED, mint.key().as_ref()], bump)]
+    pub mint_rule: UncheckedAccount<'info>,
+
+    /// Token program owning the mint (SPL Token or Token-2022)
+    pub token_program: Interface<'info, TokenInterface>,
+
+    /// System program (required for account creation)
+    pub system_program: Program<'info, System>,
+
+    /// Rent system variable (to check account rent exemption)
+    pub rent: Sysvar<'info, Rent>,
+}
+
+/// Accounts required for the `withdraw_escrow` instruction
+#[derive(Accounts)]
+pub struct WithdrawEscrow<'info> {
+    /// The escrow account being accessed (closed to the depositor on release)
+    #[account(
+        mut,
+        close = depositor,
+        seeds = [
+            ESCROW_SEED,
+            escrow.depositor.as_ref(),
+            escrow.recipient.as_ref(),
+            escrow.mint.as_ref(),
+            &escrow.escrow_id.to_le_bytes(),
+        ],
+        bump = escrow.bump,
+        has_one = depositor @ EscrowError::InvalidDepositor,
+        has_one = mint @ EscrowError::InvalidMint,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    /// The depositor of the escrow (must sign the transaction)
+    #[account(mut)]
+    pub depositor: Signer<'info>,
+
+    /// The recipient wallet recorded in the escrow
+    /// CHECK: Checked against the escrow; only used as the token account owner
+    #[account(address = escrow.recipient @ EscrowError::InvalidRecipient)]
+    pub recipient: UncheckedAccount<'info>,
+
+    /// Mint of the escrowed token (SPL Token or Token-2022), writable to harvest withheld transfer fees
+    #[account(mut, mint::token_program = token_program)]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Vault token account holding the escrowed tokens
+    #[account(
+        mut,
+        seeds = [VAULT_SEED, escrow.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = escrow,
+        token::token_program = token_program,
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Associated token account of the recipient (created if missing)
+    #[account(
+        init_if_needed,
+        payer = depositor,
+        associated_token::mint = mint,
+        associated_token::authority = recipient,
+        associated_token::token_program = token_program,
+    )]
+    pub recipient_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The program config holding the fee settings
+    #[account(seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Box<Account<'info, Config>>,
+
+    /// Treasury token account collecting protocol fees for the mint (created if missing)
+    #[account(
+        init_if_needed,
+        payer = depositor,
+        seeds = [TREASURY_SEED, mint.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = config,
+        token::token_program = token_program,
+    )]
+    pub treasury: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Token program owning the mint (SPL Token or Token-2022)
+    pub token_program: Interface<'info, TokenInterface>,
+
+    /// Associated token program (to create the recipient's token account)
+    pub associated_token_program: Program<'info, AssociatedToken>,
+
+    /// System program (required for account creation)
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts required for the `release_milestone` instruction
+#[derive(Accounts)]
+pub struct ReleaseMilestone<'info> {
+    /// The escrow account being released (closed to the depositor after the last milestone)
+    #[account(
+        mut,
+        seeds = [
+            ESCROW_SEED,
+            escrow.depositor.as_ref(),
+            escrow.recipient.as_ref(),
+            escrow.mint.as_ref(),
+            &escrow.escrow_id.to_le_bytes(),
+        ],
+        bump = escrow.bump,
+        has_one = depositor @ EscrowError::InvalidDepositor,
+        has_one = mint @ EscrowError::InvalidMint,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    /// The depositor of the escrow (must sign the transaction)
+    #[account(mut)]
+    pub depositor: Signer<'info>,
+
+    /// The recipient wallet recorded in the escrow
+    /// CHECK: Checked against the escrow; only used as the token account owner
+    #[account(address = escrow.recipient @ EscrowError::InvalidRecipient)]
+    pub recipient: UncheckedAccount<'info>,
+
+    /// Mint of the escrowed token (SPL Token or Token-2022), writable to harvest withheld transfer fees
+    #[account(mut, mint::token_program = token_program)]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Vault token account holding the escrowed tokens
+    #[account(
+        mut,
+        seeds = [VAULT_SEED, escrow.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = escrow,
+        token::token_program = token_program,
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Associated token account of the recipient (created if missing)
+    #[account(
+        init_if_needed,
+        payer = depositor,
+        associated_token::mint = mint,
+        associated_token::authority = recipient,
+        associated_token::token_program = token_program,
+    )]
+    pub recipient_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The program config holding the fee settings
+    #[account(seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Box<Account<'info, Config>>,
+
+    /// Treasury token account collecting protocol fees for the mint (created if missing)
+    #[account(
+        init_if_needed,
+        payer = depositor,
+        seeds = [TREASURY_SEED, mint.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = config,
+        token::token_program = token_program,
+    )]
+    pub treasury: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Token program owning the mint (SPL Token or Token-2022)
+    pub token_program: Interface<'info, TokenInterface>,
+
+    /// Associated token program (to create the recipient's token account)
+    pub associated_token_program: Program<'info, AssociatedToken>,
+
+    /// System program (required for account creation)
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts required for the `claim` instruction
+#[derive(Accounts)]
+pub struct Claim<'info> {
+    /// The escrow account being claimed (closed to the depositor on release)
+    #[account(
+        mut,
+        close = depositor,
+        seeds = [
+            ESCROW_SEED,
+            escrow.depositor.as_ref(),
+            escrow.recipient.as_ref(),
+            escrow.mint.as_ref(),
+            &escrow.escrow_id.to_le_bytes(),
+        ],
+        bump = escrow.bump,
+        has_one = mint @ EscrowError::InvalidMint,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    /// The recipient of the escrow (must sign the transaction)
+    #[account(mut, address = escrow.recipient @ EscrowError::InvalidRecipient)]
+    pub recipient: Signer<'info>,
+
+    /// The depositor of the escrow, receives the reclaimed rent
+    #[account(mut, address = escrow.depositor @ EscrowError::InvalidDepositor)]
+    pub depositor: SystemAccount<'info>,
+
+    /// Mint of the escrowed token (SPL Token or Token-2022), writable to harvest withheld transfer fees
+    #[account(mut, mint::token_program = token_program)]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Vault token account holding the escrowed tokens
+    #[account(
+        mut,
+        seeds = [VAULT_SEED, escrow.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = escrow,
+        token::token_program = token_program,
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Associated token account of the recipient (created if missing)
+    #[account(
+        init_if_needed,
+        payer = recipient,
+        associated_token::mint = mint,
+        associated_token::authority = recipient,
+        associated_token::token_program = token_program,
+    )]
+    pub recipient_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The program config holding the fee settings
+    #[account(seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Box<Account<'info, Config>>,
+
+    /// Treasury token account collecting protocol fees for the mint (created if missing)
+    #[account(
+        init_if_needed,
+        payer = recipient,
+        seeds = [TREASURY_SEED, mint.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = config,
+        token::token_program = token_program,
+    )]
+    pub treasury: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Token program owning the mint (SPL Token or Token-2022)
+    pub token_program: Interface<'info, TokenInterface>,
+
+    /// Associated token program (to create the recipient's token account)
+    pub associated_token_program: Program<'info, AssociatedToken>,
+
+    /// System program (required for account creation)
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts required for the `claim_vested` instruction
+#[derive(Accounts)]
+pub struct ClaimVested<'info> {
+    /// The escrow account being claimed (closed to the depositor once fully claimed)
+    #[account(
+        mut,
+        seeds = [
+            ESCROW_SEED,
+            escrow.depositor.as_ref(),
+            escrow.recipient.as_ref(),
+            escrow.mint.as_ref(),
+            &escrow.escrow_id.to_le_bytes(),
+        ],
+        bump = escrow.bump,
+        has_one = mint @ EscrowError::InvalidMint,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    /// The recipient of the escrow (must sign the transaction)
+    #[account(mut, address = escrow.recipient @ EscrowError::InvalidRecipient)]
+    pub recipient: Signer<'info>,
+
+    /// The depositor of the escrow, receives the reclaimed rent
+    #[account(mut, address = escrow.depositor @ EscrowError::InvalidDepositor)]
+    pub depositor: SystemAccount<'info>,
+
+    /// Mint of the escrowed token (SPL Token or Token-2022), writable to harvest withheld transfer fees
+    #[account(mut, mint::token_program = token_program)]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Vault token account holding the escrowed tokens
+    #[account(
+        mut,
+        seeds = [VAULT_SEED, escrow.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = escrow,
+        token::token_program = token_program,
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Associated token account of the recipient (created if missing)
+    #[account(
+        init_if_needed,
+        payer = recipient,
+        associated_token::mint = mint,
+        associated_token::authority = recipient,
+        associated_token::token_program = token_program,
+    )]
+    pub recipient_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The program config holding the fee settings
+    #[account(seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Box<Account<'info, Config>>,
+
+    /// Treasury token account collecting protocol fees for the mint (created if missing)
+    #[account(
+        init_if_needed,
+        payer = recipient,
+        seeds = [TREASURY_SEED, mint.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = config,
+        token::token_program = token_program,
+    )]
+    pub treasury: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Token program owning the mint (SPL Token or Token-2022)
+    pub token_program: Interface<'info, TokenInterface>,
+
+    /// Associated token program (to create the recipient's token account)
+    pub associated_token_program: Program<'info, AssociatedToken>,
+
+    /// System program (required for account creation)
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts required for the `cancel_escrow` instruction
+#[derive(Accounts)]
+pub struct CancelEscrow<'info> {
+    /// The escrow account being cancelled (closed to the depositor)
+    #[account(
+        mut,
+        close = depositor,
+        seeds = [
+            ESCROW_SEED,
+            escrow.depositor.as_ref(),
+            escrow.recipient.as_ref(),
+            escrow.mint.as_ref(),
+            &escrow.escrow_id.to_le_bytes(),
+        ],
+        bump = escrow.bump,
+        has_one = mint @ EscrowError::InvalidMint,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    /// The depositor or the recipient cancelling the escrow
+    pub authority: Signer<'info>,
+
+    /// The depositor of the escrow, receives the reclaimed rent
+    #[account(mut, address = escrow.depositor @ EscrowError::InvalidDepositor)]
+    pub depositor: SystemAccount<'info>,
+
+    /// Mint of the escrowed token (SPL Token or Token-2022), writable to harvest withheld transfer fees
+    #[account(mut, mint::token_program = token_program)]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Vault token account holding the escrowed tokens
+    #[account(
+        mut,
+        seeds = [VAULT_SEED, escrow.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = escrow,
+        token::token_program = token_program,
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Token account of the depositor receiving the refund
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = escrow.depositor,
+        token::token_program = token_program,
+    )]
+    pub depositor_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Token program owning the mint (SPL Token or Token-2022)
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+/// Accounts required for the `approve_release` instruction
+#[derive(Accounts)]
+pub struct ApproveRelease<'info> {
+    /// The escrow account being approved
+    #[account(
+        mut,
+        seeds = [
+            ESCROW_SEED,
+            escrow.depositor.as_ref(),
+            escrow.recipient.as_ref(),
+            escrow.mint.as_ref(),
+            &escrow.escrow_id.to_le_bytes(),
+        ],
+        bump = escrow.bump,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    /// One of the escrow's approvers (must sign the transaction)
+    pub approver: Signer<'info>,
+}
+
+/// Accounts required for the `raise_dispute` instruction
+#[derive(Accounts)]
+pub struct RaiseDispute<'info> {
+    /// The escrow account being disputed
+    #[account(
+        mut,
+        seeds = [
+            ESCROW_SEED,
+            escrow.depositor.as_ref(),
+            escrow.recipient.as_ref(),
+            escrow.mint.as_ref(),
+            &escrow.escrow_id.to_le_bytes(),
+        ],
+        bump = escrow.bump,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    /// The depositor or the recipient raising the dispute
+    pub authority: Signer<'info>,
+}
+
+/// Accounts required for the `resolve_dispute` instruction
+#[derive(Accounts)]
+pub struct ResolveDispute<'info> {
+    /// The escrow account being resolved (closed to the depositor)
+    #[account(
+        mut,
+        close = depositor,
+        seeds = [
+            ESCROW_SEED,
+            escrow.depositor.as_ref(),
+            escrow.recipient.as_ref(),
+            escrow.mint.as_ref(),
+            &escrow.escrow_id.to_le_bytes(),
+        ],
+        bump = escrow.bump,
+        has_one = mint @ EscrowError::InvalidMint,
+        constraint = escrow.arbiter == Some(arbiter.key()) @ EscrowError::InvalidArbiter,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    /// The arbiter of the escrow (must sign the transaction)
+    #[account(mut)]
+    pub arbiter: Signer<'info>,
+
+    /// The depositor of the escrow, receives the reclaimed rent
+    #[account(mut, address = escrow.depositor @ EscrowError::InvalidDepositor)]
+    pub depositor: SystemAccount<'info>,
+
+    /// The recipient wallet recorded in the escrow
+    /// CHECK: Checked against the escrow; only used as the token account owner
+    #[account(address = escrow.recipient @ EscrowError::InvalidRecipient)]
+    pub recipient: UncheckedAccount<'info>,
+
+    /// Mint of the escrowed token (SPL Token or Token-2022), writable to harvest withheld transfer fees
+    #[account(mut, mint::token_program = token_program)]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Vault token account holding the escrowed tokens
+    #[account(
+        mut,
+        seeds = [VAULT_SEED, escrow.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = escrow,
+        token::token_program = token_program,
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Token account of the depositor receiving the depositor's share
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = depositor,
+        token::token_program = token_program,
+    )]
+    pub depositor_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Associated token account of the recipient (created if missing)
+    #[account(
+        init_if_needed,
+        payer = arbiter,
+        associated_token::mint = mint,
+        associated_token::authority = recipient,
+        associated_token::token_program = token_program,
+    )]
+    pub recipient_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The program config holding the fee settings
+    #[account(seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Box<Account<'info, Config>>,
+
+    /// Treasury token account collecting protocol fees for the mint (created if missing)
+    #[account(
+        init_if_needed,
+        payer = arbiter,
+        seeds = [TREASURY_SEED, mint.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = config,
+        token::token_program = token_program,
+    )]
+    pub treasury: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Token program owning the mint (SPL Token or Token-2022)
+    pub token_program: Interface<'info, TokenInterface>,
+
+    /// Associated token program (to create the recipient's token account)
+    pub associated_token_program: Program<'info, AssociatedToken>,
+
+    /// System program (required for account creation)
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts required for the `make_offer` instruction
+#[derive(Accounts)]
+#[instruction(args: MakeOfferArgs)]
+pub struct MakeOffer<'info> {
+    /// The escrow account holding the offer (PDA of maker, taker, offered mint and escrow id)
+    #[account(
+        init,
+        payer = maker,
+        space = Escrow::LEN,
+        seeds = [
+            ESCROW_SEED,
+            maker.key().as_ref(),
+            args.taker.unwrap_or_default().as_ref(),
+            mint.key().as_ref(),
+            &args.escrow_id.to_le_bytes(),
+        ],
+        bump,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    /// The maker of the offer (payer of account rent)
+    #[account(mut)]
+    pub maker: Signer<'info>,
+
+    /// Mint of the offered token A (SPL Token or Token-2022)
+    #[account(mint::token_program = token_program)]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Mint of the wanted token B (SPL Token or Token-2022)
+    pub wanted_mint: InterfaceAccount<'info, Mint>,
+
+    /// Token account of the maker the offered tokens are taken from
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = maker,
+        token::token_program = token_program,
+    )]
+    pub maker_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Vault token account holding the offered tokens (PDA, owned by the escrow PDA)
+    #[account(
+        init,
+        payer = maker,
+        seeds = [VAULT_SEED, escrow.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = escrow,
+        token::token_program = token_program,
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The program config holding the pause flag and mint policy
+    #[account(seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Box<Account<'info, Config>>,
+
+    /// Rule PDA of the mint (pause flag and listing), if one was ever set
+    /// CHECK: Address checked by the seeds; read through MintRule::load as it may not exist
+    #[account(seeds = [MINT_RULE_SEED, mint.key().as_ref()], bump)]
+    pub mint_rule: UncheckedAccount<'info>,
+
+    /// Rule PDA of the wanted mint (pause flag and listing), if one was ever set
+    /// CHECK: Address checked by the seeds; read through MintRule::load as it may not exist
+    #[account(seeds = [MINT_RULE_SEED, wanted_mint.key().as_ref()], bump)]
+    pub wanted_mint_rule: UncheckedAccount<'info>,
+
+    /// Token program owning the offered mint (SPL Token or Token-2022)
+    pub token_program: Interface<'info, TokenInterface>,
+
+    /// System program (required for account creation)
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts required for the `take_offer` instruction
+#[derive(Accounts)]
+pub struct TakeOffer<'info> {
+    /// The escrow account holding the offer (closed to the maker once fully filled)
+    #[account(
+        mut,
+        seeds = [
+            ESCROW_SEED,
+            escrow.depositor.as_ref(),
+            escrow.recipient.as_ref(),
+            escrow.mint.as_ref(),
+            &escrow.escrow_id.to_le_bytes(),
+        ],
+        bump = escrow.bump,
+        has_one = mint @ EscrowError::InvalidMint,
+        has_one = wanted_mint @ EscrowError::InvalidMint,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    /// The taker of the offer (must sign the transaction)
+    #[account(mut)]
+    pub taker: Signer<'info>,
+
+    /// The maker of the offer, receives token B and the reclaimed rent
+    #[account(mut, address = escrow.depositor @ EscrowError::InvalidDepositor)]
+    pub maker: SystemAccount<'info>,
+
+    /// Mint of the offered token A (SPL Token or Token-2022), writable to harvest withheld transfer fees
+    #[account(mut, mint::token_program = token_program)]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Mint of the wanted token B (SPL Token or Token-2022)
+    #[account(mint::token_program = wanted_token_program)]
+    pub wanted_mint: InterfaceAccount<'info, Mint>,
+
+    /// Vault token account holding the offered tokens
+    #[account(
+        mut,
+        seeds = [VAULT_SEED, escrow.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = escrow,
+        token::token_program = token_program,
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Token account of the taker the wanted tokens are taken from
+    #[account(
+        mut,
+        token::mint = wanted_mint,
+        token::authority = taker,
+        token::token_program = wanted_token_program,
+    )]
+    pub taker_wanted_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Associated token account of the maker receiving the wanted tokens (created if missing)
+    #[account(
+        init_if_needed,
+        payer = taker,
+        associated_token::mint = wanted_mint,
+        associated_token::authority = maker,
+        associated_token::token_program = wanted_token_program,
+    )]
+    pub maker_wanted_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Associated token account of the taker receiving the offered tokens (created if missing)
+    #[account(
+        init_if_needed,
+        payer = taker,
+        associated_token::mint = mint,
+        associated_token::authority = taker,
+        associated_token::token_program = token_program,
+    )]
+    pub taker_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The program config holding the pause flag and fee settings
+    #[account(seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Box<Account<'info, Config>>,
+
+    /// Treasury token account collecting protocol fees for the offered mint (created if missing)
+    #[account(
+        init_if_needed,
+        payer = taker,
+        seeds = [TREASURY_SEED, mint.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = config,
+        token::token_program = token_program,
+    )]
+    pub treasury: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Token program owning the offered mint (SPL Token or Token-2022)
+    pub token_program: Interface<'info, TokenInterface>,
+
+    /// Token program owning the wanted mint (SPL Token or Token-2022)
+    pub wanted_token_program: Interface<'info, TokenInterface>,
+
+    /// Associated token program (to create the receiving token accounts)
+    pub associated_token_program: Program<'info, AssociatedToken>,
+
+    /// System program (required for account creation)
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts required for the `create_native_escrow` instruction
+#[derive(Accounts)]
+#[instruction(args: CreateNativeEscrowArgs)]
+pub struct CreateNativeEscrow<'info> {
+    /// The escrow account holding the lamports (PDA of depositor, recipient and escrow id)
+    #[account(
+        init,
+        payer = depositor,
+        space = Escrow::LEN,
+        seeds = [
+            ESCROW_SEED,
+            depositor.key().as_ref(),
+            recipient.key().as_ref(),
+            Pubkey::default().as_ref(),
+            &args.escrow_id.to_le_bytes(),
+        ],
+        bump,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    /// The depositor creating the escrow (payer of the lamports and account rent)
+    #[account(mut)]
+    pub depositor: Signer<'info>,
+
+    /// The recipient of the escrowed lamports
+    pub recipient: SystemAccount<'info>,
+
+    /// The program config holding the pause flag
+    #[account(seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Box<Account<'info, Config>>,
+
+    /// System program (required for account creation and the deposit)
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts required for the `withdraw_native_escrow` instruction
+#[derive(Accounts)]
+pub struct WithdrawNativeEscrow<'info> {
+    /// The escrow account holding the lamports (closed to the depositor)
+    #[account(
+        mut,
+        close = depositor,
+        seeds = [
+            ESCROW_SEED,
+            escrow.depositor.as_ref(),
+            escrow.recipient.as_ref(),
+            escrow.mint.as_ref(),
+            &escrow.escrow_id.to_le_bytes(),
+        ],
+        bump = escrow.bump,
+        has_one = depositor,
+        has_one = recipient @ EscrowError::InvalidRecipient,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    /// The depositor releasing the escrow (must sign the transaction)
+    #[account(mut)]
+    pub depositor: Signer<'info>,
+
+    /// The recipient receiving the escrowed lamports
+    #[account(mut)]
+    pub recipient: SystemAccount<'info>,
+}
+
+/// Accounts required for the `cancel_native_escrow` instruction
+#[derive(Accounts)]
+pub struct CancelNativeEscrow<'info> {
+    /// The escrow account being cancelled (closed to the depositor with the escrowed lamports)
+    #[account(
+        mut,
+        close = depositor,
+        seeds = [
+            ESCROW_SEED,
+            escrow.depositor.as_ref(),
+            escrow.recipient.as_ref(),
+            escrow.mint.as_ref(),
+            &escrow.escrow_id.to_le_bytes(),
+        ],
+        bump = escrow.bump,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    /// The depositor or the recipient cancelling the escrow
+    pub authority: Signer<'info>,
+
+    /// The depositor of the escrow, receives the refund and the reclaimed rent
+    #[account(mut, address = escrow.depositor @ EscrowError::InvalidDepositor)]
+    pub depositor: SystemAccount<'info>,
+}
+
+/// Accounts required for the `initialize_config` instruction
+#[derive(Accounts)]
+pub struct InitializeConfig<'info> {
+    /// The program config (singleton PDA)
+    #[account(init, payer = authority, space = Config::LEN, seeds = [CONFIG_SEED], bump)]
+    pub config: Account<'info, Config>,
+
+    /// The program's upgrade authority (payer of account rent)
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    /// This program, used to find its program data account
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, crate::program::EscrowSolana>,
+
+    /// Program data account holding the upgrade authority
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(authority.key()) @ EscrowError::Unauthorized
+    )]
+    pub program_data: Account<'info, ProgramData>,
+
+    /// System program (required for account creation)
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts required for the `update_config` and `transfer_admin` instructions
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    /// The program config
+    #[account(mut, seeds = [CONFIG_SEED], bump = config.bump, has_one = admin @ EscrowError::Unauthorized)]
+    pub config: Account<'info, Config>,
+
+    /// The current admin (must sign the transaction)
+    pub admin: Signer<'info>,
+}
+
+/// Accounts required for the `accept_admin` instruction
+#[derive(Accounts)]
+pub struct AcceptAdmin<'info> {
+    /// The program config
+    #[account(mut, seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// The proposed admin (must sign the transaction)
+    pub new_admin: Signer<'info>,
+}
+
+/// Accounts required for the `set_paused` instruction
+#[derive(Accounts)]
+pub struct SetPaused<'info> {
+    /// The program config
+    #[account(mut, seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// The admin or the guardian (must sign the transaction)
+    pub authority: Signer<'info>,
+}
+
+/// Accounts required for the per-mint rule instructions
+#[derive(Accounts)]
+pub struct SetMintRule<'info> {
+    /// The program config
+    #[account(seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// The admin or the guardian (payer of account rent); listings and fees are admin-only
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    /// Mint the rule applies to (SPL Token or Token-2022)
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Rule PDA of the mint (created if missing)
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = MintRule::LEN,
+        seeds = [MINT_RULE_SEED, mint.key().as_ref()],
+        bump,
+    )]
+    pub mint_rule: Account<'info, MintRule>,
+
+    /// System program (required for account creation)
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts required for the `initialize_treasury` instruction
+#[derive(Accounts)]
+pub struct InitializeTreasury<'info> {
+    /// The program config, owner of every treasury
+    #[account(seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// Payer of account rent
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// Mint of the fees collected by the treasury (SPL Token or Token-2022)
+    #[account(mint::token_program = token_program)]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Treasury token account collecting protocol fees for the mint
+    #[account(
+        init,
+        payer = payer,
+        seeds = [TREASURY_SEED, mint.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = config,
+        token::token_program = token_program,
+    )]
+    pub treasury: InterfaceAccount<'info, TokenAccount>,
+
+    /// Token program owning the mint (SPL Token or Token-2022)
+    pub token_program: Interface<'info, TokenInterface>,
+
+    /// System program (required for account creation)
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts required for the `withdraw_fees` instruction
+#[derive(Accounts)]
+pub struct WithdrawFees<'info> {
+    /// The program config, owner of every treasury
+    #[account(seeds = [CONFIG_SEED], bump = config.bump, has_one = admin @ EscrowError::Unauthorized)]
+    pub config: Account<'info, Config>,
+
+    /// The admin (must sign the transaction)
+    pub admin: Signer<'info>,
+
+    /// Mint of the collected fees (SPL Token or Token-2022)
+    #[account(mint::token_program = token_program)]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Treasury token account holding the collected fees
+    #[account(
+        mut,
+        seeds = [TREASURY_SEED, mint.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = config,
+        token::token_program = token_program,
+    )]
+    pub treasury: InterfaceAccount<'info, TokenAccount>,
+
+    /// Token account receiving the fees
+    #[account(mut, token::mint = mint, token::token_program = token_program)]
+    pub destination: InterfaceAccount<'info, TokenAccount>,
+
+    /// Token program owning the mint (SPL Token or Token-2022)
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+/// Emitted when an escrow or swap offer is created
+#[event]
+pub struct EscrowCreated {
+    pub escrow: Pubkey, // Address of the escrow account
+    pub depositor: Pubkey, // Depositor (or maker) of the escrow
+    pub recipient: Pubkey, // Recipient (or designated taker, default for open offers)
+    pub mint: Pubkey, // Mint of the escrowed token (default for native SOL)
+    pub kind: u8, // Kind of escrow (see EscrowKind)
+    pub amount: u64, // Amount held by the escrow
+    pub release_after: i64, // Earliest release time (timestamp or slot)
+    pub refund_after: i64, // Refund deadline (timestamp or slot)
+    pub timestamp: i64, // Unix timestamp of the transition
+}
+
+/// Emitted for every deposit into an escrow, once per mint
+#[event]
+pub struct EscrowFunded {
+    pub escrow: Pubkey, // Address of the escrow account
+    pub depositor: Pubkey, // Depositor (or maker) of the escrow
+    pub mint: Pubkey, // Mint of the deposited token (default for native SOL)
+    pub amount: u64, // Amount that arrived in the escrow
+    pub timestamp: i64, // Unix timestamp of the transition
+}
+
+/// Emitted whenever an escrow pays out to its recipient
+#[event]
+pub struct EscrowReleased {
+    pub escrow: Pubkey, // Address of the escrow account
+    pub recipient: Pubkey, // Recipient of the escrow
+    pub mint: Pubkey, // Mint of the released token (default for native SOL)
+    pub amount: u64, // Amount of the primary mint released, after fees
+    pub fee: u64, // Protocol fee taken into the treasury
+    pub completed: bool, // Whether the escrow is completed and closed
+    pub timestamp: i64, // Unix timestamp of the transition
+}
+
+/// Emitted when an escrow is cancelled and refunded to the depositor
+#[event]
+pub struct EscrowCancelled {
+    pub escrow: Pubkey, // Address of the escrow account
+    pub depositor: Pubkey, // Depositor receiving the refund
+    pub mint: Pubkey, // Mint of the refunded token (default for native SOL)
+    pub amount: u64, // Amount of the primary mint refunded
+    pub cancelled_by: Pubkey, // Depositor or recipient who cancelled
+    pub timestamp: i64, // Unix timestamp of the transition
+}
+
+/// Emitted when an approver approves the release of an escrow
+#[event]
+pub struct ReleaseApproved {
+    pub escrow: Pubkey, // Address of the escrow account
+    pub approver: Pubkey, // Approver who signed off
+    pub approvals: u8, // Number of approvals recorded so far
+    pub approval_threshold: u8, // Number of approvals required
+    pub timestamp: i64, // Unix timestamp of the transition
+}
+
+/// Emitted when a party raises a dispute
+#[event]
+pub struct DisputeRaised {
+    pub escrow: Pubkey, // Address of the escrow account
+    pub raised_by: Pubkey, // Depositor or recipient who raised the dispute
+    pub timestamp: i64, // Unix timestamp of the transition
+}
+
+/// Emitted when the arbiter resolves a dispute
+#[event]
+pub struct DisputeResolved {
+    pub escrow: Pubkey, // Address of the escrow account
+    pub arbiter: Pubkey, // Arbiter who resolved the dispute
+    pub recipient_amount: u64, // Amount paid to the recipient, after fees
+    pub depositor_amount: u64, // Amount returned to the depositor
+    pub fee: u64, // Protocol fee taken from the recipient's share into the treasury
+    pub timestamp: i64, // Unix timestamp of the transition
+}
 
-    /// Token program ID (must match the SPL token program)
-    #[account(address = token::ID)]
-    pub token_program: Program<'info, Token>,
+/// Emitted when a swap offer is filled, in full or in part
+#[event]
+pub struct OfferTaken {
+    pub escrow: Pubkey, // Address of the escrow account
+    pub taker: Pubkey, // Taker who filled the offer
+    pub amount_in: u64, // Amount of the wanted token sent to the maker
+    pub amount_out: u64, // Amount of the offered token sent to the taker, after fees
+    pub fee: u64, // Protocol fee taken from the offered token into the treasury
+    pub completed: bool, // Whether the offer is fully filled and closed
+    pub timestamp: i64, // Unix timestamp of the transition
 }
 
 /// Custom error codes for the escrow program
@@ -136,6 +3290,80 @@ pub struct WithdrawEscrow<'info> {
 pub enum EscrowError {
     #[msg("Invalid escrow status.")] // Error for incorrect status
     InvalidStatus,
-    #[msg("Escrow expired.")] // Error if escrow is already expired
-    EscrowExpired,
+    #[msg("Escrow cannot be released yet.")] // Error if the release time has not been reached
+    ReleaseTimeNotReached,
+    #[msg("Release time must not be in the past and must precede the refund deadline.")] // Error for bad timestamps
+    InvalidTimeWindow,
+    #[msg("Escrow time calculation overflowed.")] // Error if a timestamp overflows
+    TimeOverflow,
+    #[msg("Release and refund times must use the same time basis.")] // Error for mixed slots and timestamps
+    MismatchedTimeConditions,
+    #[msg("Escrow duration exceeds the maximum allowed.")] // Error if the refund deadline is too far away
+    DurationTooLong,
+    #[msg("Escrow has too many milestones.")] // Error if more than MAX_MILESTONES are given
+    TooManyMilestones,
+    #[msg("Milestones must add up to the escrowed amount.")] // Error for inconsistent milestone amounts
+    InvalidMilestones,
+    #[msg("Milestone does not exist.")] // Error for an out-of-range milestone index
+    InvalidMilestone,
+    #[msg("Milestone has already been released.")] // Error if a milestone is paid twice
+    MilestoneAlreadyReleased,
+    #[msg("Vesting schedule is invalid.")] // Error for an unordered schedule or one combined with milestones
+    InvalidVestingSchedule,
+    #[msg("Vesting escrows must be claimed with claim_vested.")] // Error if claim is used on a vesting escrow
+    VestingEscrow,
+    #[msg("Escrow has no vesting schedule.")] // Error if claim_vested is used on a regular escrow
+    NotVestingEscrow,
+    #[msg("Nothing has vested since the last claim.")] // Error if there is nothing to claim
+    NothingToClaim,
+    #[msg("Vested amount calculation overflowed.")] // Error if vesting math overflows
+    VestingOverflow,
+    #[msg("Escrow has no arbiter.")] // Error if a dispute is raised without an arbiter
+    NoArbiter,
+    #[msg("Arbiter does not match the escrow.")] // Error if someone else tries to resolve a dispute
+    InvalidArbiter,
+    #[msg("Basis points must not exceed 10,000.")] // Error for a split above 100%
+    InvalidBasisPoints,
+    #[msg("Approvers must be unique and within the maximum allowed.")] // Error for a bad approver list
+    InvalidApprovers,
+    #[msg("Approval threshold must be between 1 and the number of approvers.")] // Error for an unreachable threshold
+    InvalidApprovalThreshold,
+    #[msg("Signer is not an approver of the escrow.")] // Error if a non-approver tries to approve
+    InvalidApprover,
+    #[msg("Approver has already approved the release.")] // Error if an approver approves twice
+    AlreadyApproved,
+    #[msg("Instruction does not apply to this kind of escrow.")] // Error for transfer/offer mix-ups
+    InvalidEscrowKind,
+    #[msg("Fill amount is zero, exceeds the offer or buys nothing.")] // Error for invalid partial fills
+    InvalidFillAmount,
+    #[msg("Mint is not an NFT or the escrow is not for exactly one token.")] // Error for invalid NFT escrows
+    InvalidNft,
+    #[msg("Basket legs or their accounts are invalid.")] // Error for invalid basket escrows
+    InvalidBasket,
+    #[msg("Recipient shares or their token accounts are invalid.")] // Error for invalid split escrows
+    InvalidSplits,
+    #[msg("Protocol fee could not be computed.")] // Error for fee overflows
+    InvalidFee,
+    #[msg("The program is paused.")] // Error for funding while paused
+    ProgramPaused,
+    #[msg("Escrows of this mint are paused.")] // Error for funding a paused mint
+    MintPaused,
+    #[msg("This mint may not be escrowed under the mint policy.")] // Error for unlisted or denylisted mints
+    MintNotAllowed,
+    #[msg("Milestone escrows are released by the depositor through release_milestone.")] // Error for claiming milestone escrows
+    MilestoneEscrow,
+    #[msg("Protocol fee exceeds the maximum allowed.")] // Error for a fee rate above MAX_FEE_BPS
+    FeeTooHigh,
+    #[msg("Escrow amount must be greater than zero.")] // Error for an empty deposit
+    InvalidAmount,
+    #[msg("Recipient does not match the escrow.")] // Error if the claimant is not the recipient
+    InvalidRecipient,
+    #[msg("Depositor does not match the escrow.")] // Error if the rent receiver is not the depositor
+    InvalidDepositor,
+    #[msg("Mint does not match the escrow.")] // Error if a different token is supplied
+    InvalidMint,
+    #[msg("Signer is not a party to the escrow.")] // Error if neither depositor nor recipient signed
+    Unauthorized,
+    #[msg("Escrow cannot be cancelled by the depositor now.")] // Error if the cancel policy forbids it
+    CancelNotAllowed,
 }
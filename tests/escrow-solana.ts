@@ -1,14 +1,26 @@
 import * as anchor from "@coral-xyz/anchor";
 import { Program } from "@coral-xyz/anchor";
-import { PublicKey, Keypair, SystemProgram } from "@solana/web3.js";
+import {
+  PublicKey,
+  Keypair,
+  SystemProgram,
+  Transaction,
+} from "@solana/web3.js";
 import {
   TOKEN_2022_PROGRAM_ID,
   TOKEN_PROGRAM_ID,
+  ExtensionType,
+  createInitializeMintInstruction,
+  createInitializeTransferFeeConfigInstruction,
+  getMintLen,
   createMint,
   mintTo,
   createAccount,
   createAssociatedTokenAccount,
+  createAssociatedTokenAccountIdempotentInstruction,
   getAssociatedTokenAddress,
+  getAssociatedTokenAddressSync,
+  ASSOCIATED_TOKEN_PROGRAM_ID,
 } from "@solana/spl-token";
 import { expect } from "chai";
 import { EscrowSolana } from "../target/types/escrow_solana";
@@ -71,18 +83,172 @@ describe("escrow_solana", () => {
   // Keypairs and accounts
   const depositor = Keypair.generate();
   const recipient = Keypair.generate();
+  const arbiter = Keypair.generate();
   let mint: PublicKey;
   let depositorTokenAccount: PublicKey;
   let recipientTokenAccount: PublicKey;
   let pda: PublicKey;
   let escrowTokenAccount: PublicKey;
   let escrowBump: number;
+  const escrowId = new anchor.BN(1);
+  const [config] = PublicKey.findProgramAddressSync(
+    [Buffer.from("config")],
+    program.programId
+  );
+  const amount = 500_000_000; // 0.5 tokens (9 decimals)
+  const maxDuration = 5 * 365 * 24 * 60 * 60; // 5 years, in seconds
+
+  // Derives the escrow PDA, its bump and its vault for an escrow id
+  function findEscrow(
+    id: anchor.BN,
+    escrowMint: PublicKey = mint
+  ): [PublicKey, number, PublicKey] {
+    const [escrow, bump] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("escrow"),
+        depositor.publicKey.toBuffer(),
+        recipient.publicKey.toBuffer(),
+        escrowMint.toBuffer(),
+        id.toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    const [vault] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), escrow.toBuffer()],
+      program.programId
+    );
+    return [escrow, bump, vault];
+  }
+
+  // Derives the treasury collecting protocol fees for a mint
+  function findTreasury(feeMint: PublicKey): PublicKey {
+    const [treasury] = PublicKey.findProgramAddressSync(
+      [Buffer.from("treasury"), feeMint.toBuffer()],
+      program.programId
+    );
+    return treasury;
+  }
+
+  // Derives the rule PDA the admin or guardian can set for a mint
+  function findMintRule(ruleMint: PublicKey): PublicKey {
+    const [mintRule] = PublicKey.findProgramAddressSync(
+      [Buffer.from("mint_rule"), ruleMint.toBuffer()],
+      program.programId
+    );
+    return mintRule;
+  }
+
+  // Parses the events a confirmed transaction emitted
+  async function eventsOf(signature: string) {
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const parser = new anchor.EventParser(program.programId, program.coder);
+    return [...parser.parseLogs(tx.meta.logMessages)];
+  }
+
+  // Creates and funds an escrow for the recipient
+  async function createEscrow(
+    id: anchor.BN,
+    releaseAfter: number,
+    {
+      refundAfter = releaseAfter + 3600,
+      cancelPolicy = { beforeRelease: {} } as object,
+      milestones = [] as number[],
+      vesting = null as { start: number; cliff: number; end: number } | null,
+      arbiter = null as PublicKey | null,
+      approvers = [] as PublicKey[],
+      approvalThreshold = 0,
+      basket = [] as { mint: PublicKey; amount: number }[],
+      splits = [] as { recipient: PublicKey; bps: number }[],
+      escrowMint = mint,
+      nft = false, // Locks the single token of `escrowMint`
+    } = {}
+  ): Promise<[PublicKey, PublicKey, string]> {
+    const [escrow, , vault] = findEscrow(id, escrowMint);
+
+    // Each basket leg is held in the escrow's associated token account for its mint
+    const basketVaults = basket.map(({ mint }) =>
+      getAssociatedTokenAddressSync(mint, escrow, true, TOKEN_2022_PROGRAM_ID)
+    );
+    const signature = await program.methods
+      .createEscrow({
+        escrowId: id,
+        amount: new anchor.BN(nft ? 1 : amount),
+        releaseAfter: { relativeSeconds: [new anchor.BN(releaseAfter)] },
+        refundAfter: { relativeSeconds: [new anchor.BN(refundAfter)] },
+        cancelPolicy,
+        milestones: milestones.map((m) => new anchor.BN(m)),
+        vesting: vesting && {
+          start: new anchor.BN(vesting.start),
+          cliff: new anchor.BN(vesting.cliff),
+          end: new anchor.BN(vesting.end),
+        },
+        arbiter,
+        approvers,
+        approvalThreshold,
+        nft,
+        basket: basket.map((leg) => new anchor.BN(leg.amount)),
+        splits,
+      } as any)
+      .accountsStrict({
+        escrow,
+        depositor: depositor.publicKey,
+        recipient: recipient.publicKey,
+        mint: escrowMint,
+        depositorTokenAccount: getAssociatedTokenAddressSync(
+          escrowMint,
+          depositor.publicKey,
+          false,
+          TOKEN_2022_PROGRAM_ID
+        ),
+        vault,
+        config,
+        mintRule: findMintRule(escrowMint),
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      })
+      .remainingAccounts(
+        basket.flatMap(({ mint }, index) => [
+          { pubkey: mint, isSigner: false, isWritable: false },
+          {
+            pubkey: getAssociatedTokenAddressSync(
+              mint,
+              depositor.publicKey,
+              false,
+              TOKEN_2022_PROGRAM_ID
+            ),
+            isSigner: false,
+            isWritable: true,
+          },
+          { pubkey: basketVaults[index], isSigner: false, isWritable: true },
+          { pubkey: findMintRule(mint), isSigner: false, isWritable: false },
+        ])
+      )
+      .preInstructions(
+        basket.map(({ mint }, index) =>
+          createAssociatedTokenAccountIdempotentInstruction(
+            depositor.publicKey,
+            basketVaults[index],
+            escrow,
+            mint,
+            TOKEN_2022_PROGRAM_ID
+          )
+        )
+      )
+      .signers([depositor])
+      .rpc({ commitment: "confirmed" });
+    return [escrow, vault, signature];
+  }
 
   before(async () => {
     // Airdrop SOL to the depositor and recipient for fees
     try {
       await airdropSol(depositor.publicKey, 2); // Airdrop 2 SOL to depositor
       await airdropSol(recipient.publicKey, 2); // Airdrop 2 SOL to recipient
+      await airdropSol(arbiter.publicKey, 1); // Airdrop 1 SOL to arbiter
     } catch (error) {
       console.log("Airdrop failed:", error);
     }
@@ -108,24 +274,12 @@ describe("escrow_solana", () => {
       TOKEN_2022_PROGRAM_ID // Correct Token Program ID
     );
 
-    // depositorTokenAccount = await createAccount(
-    //   provider.connection,
-    //   depositor, // Payer to create Token Account
-    //   mint, // Mint Account address
-    //   depositor.publicKey, // Token Account owner
-    //   undefined, // Optional keypair, default to Associated Token Account
-    //   undefined, // Confirmation options
-    //   TOKEN_2022_PROGRAM_ID // Token Extension Program ID
-    // );
-
-    recipientTokenAccount = await createAccount(
-      provider.connection,
-      recipient, // Payer to create Token Account
-      mint, // Mint Account address
-      recipient.publicKey, // Token Account owner
-      undefined, // Optional keypair, default to Associated Token Account
-      undefined, // Confirmation options
-      TOKEN_2022_PROGRAM_ID // Token Extension Program ID
+    // The recipient's associated token account is created by the program on payout
+    recipientTokenAccount = getAssociatedTokenAddressSync(
+      mint,
+      recipient.publicKey,
+      false,
+      TOKEN_2022_PROGRAM_ID
     );
 
     // Mint tokens to the depositor's token account
@@ -135,124 +289,1457 @@ describe("escrow_solana", () => {
       mint,
       depositorTokenAccount,
       depositor.publicKey,
-      1_000_000_000, // Mint tokens to the depositor's account
+      2_000_000_000, // Mint tokens to the depositor's account
       undefined,
       undefined,
       TOKEN_2022_PROGRAM_ID
     );
+
+    [pda, escrowBump, escrowTokenAccount] = findEscrow(escrowId);
+
+    // The provider wallet deployed the program, so it is the upgrade authority
+    const [programData] = PublicKey.findProgramAddressSync(
+      [program.programId.toBuffer()],
+      new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111")
+    );
+    await program.methods
+      .initializeConfig({
+        feeBps: 0,
+        mintPolicy: { allowAll: {} },
+        paused: false,
+        guardian: arbiter.publicKey,
+        maxDuration: new anchor.BN(maxDuration),
+      } as any)
+      .accountsStrict({
+        config,
+        authority: provider.wallet.publicKey,
+        program: program.programId,
+        programData,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
   });
 
   it("Creates an escrow", async () => {
-    let [pda, bump] = PublicKey.findProgramAddressSync(
-      [Buffer.from("escrow"), depositor.publicKey.toBuffer()],
-      program.programId
+    await createEscrow(escrowId, 0); // Withdrawable immediately
+
+    const escrow = await program.account.escrow.fetch(pda);
+    expect(escrow.depositor.toBase58()).to.equal(
+      depositor.publicKey.toBase58()
+    );
+    expect(escrow.recipient.toBase58()).to.equal(
+      recipient.publicKey.toBase58()
     );
+    expect(escrow.mint.toBase58()).to.equal(mint.toBase58());
+    expect(escrow.amount.toNumber()).to.equal(amount);
+    expect(escrow.status).to.equal(0); // Pending
+    expect(escrow.bump).to.equal(escrowBump);
+  });
 
-    console.log(`bump: ${bump}, pubkey: ${pda.toBase58()}`);
+  it("Funds the escrow", async () => {
+    const escrow = await program.account.escrow.fetch(pda);
+    expect(escrow.status).to.equal(0); // Still Pending
 
-    const isOnCurve = PublicKey.isOnCurve(pda.toBuffer());
-    console.log("Is escrowAccount on curve:", isOnCurve);
+    // Check balances
+    const escrowTokenBalance = await provider.connection.getTokenAccountBalance(
+      escrowTokenAccount
+    );
+    expect(escrowTokenBalance.value.amount).to.equal(amount.toString());
+  });
 
-    let accountInfo = await provider.connection.getParsedAccountInfo(pda);
-    console.log("Escrow Token Account Info:", accountInfo);
+  it("Withdraws from the escrow", async () => {
+    await program.methods
+      .withdrawEscrow()
+      .accountsStrict({
+        escrow: pda,
+        depositor: depositor.publicKey,
+        recipient: recipient.publicKey,
+        mint,
+        vault: escrowTokenAccount,
+        recipientTokenAccount,
+        config,
+        treasury: findTreasury(mint),
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([depositor])
+      .rpc();
 
-    console.log("programId: ", program.programId);
+    // Escrow and vault are closed once settled
+    expect(await program.account.escrow.fetchNullable(pda)).to.be.null;
+    expect(await provider.connection.getAccountInfo(escrowTokenAccount)).to.be
+      .null;
 
-    escrowTokenAccount = await getAssociatedTokenAddress(
-      mint, // Mint of the token
-      pda, // Owner/authority (PDA)
-      true, // Allow the owner account to be a PDA
-      SystemProgram.programId, // Solana connection
-      TOKEN_2022_PROGRAM_ID // Payer of the transaction
-    );
+    const recipientTokenBalance =
+      await provider.connection.getTokenAccountBalance(recipientTokenAccount);
+    expect(recipientTokenBalance.value.amount).to.equal(amount.toString());
+  });
 
-    console.log({
-      escrow: pda,
-      depositor: depositor.publicKey,
-      depositorTokenAccount,
-      escrowTokenAccount,
-      recipient: recipient.publicKey,
-      mint,
-      systemProgram: SystemProgram.programId,
-      rent: anchor.web3.SYSVAR_RENT_PUBKEY,
-      tokenProgram: TOKEN_2022_PROGRAM_ID,
-    });
+  it("Lets the recipient claim without the depositor", async () => {
+    const [claimEscrow, claimVault] = await createEscrow(new anchor.BN(2), 0);
+
+    const signature = await program.methods
+      .claim()
+      .accountsStrict({
+        escrow: claimEscrow,
+        recipient: recipient.publicKey,
+        depositor: depositor.publicKey,
+        mint,
+        vault: claimVault,
+        recipientTokenAccount,
+        config,
+        treasury: findTreasury(mint),
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([recipient])
+      .rpc({ commitment: "confirmed" });
+
+    expect(await program.account.escrow.fetchNullable(claimEscrow)).to.be.null;
+
+    const events = await eventsOf(signature);
+    expect(events.map((event) => event.name)).to.deep.equal([
+      "escrowReleased",
+    ]);
+    expect(events[0].data.escrow.toBase58()).to.equal(claimEscrow.toBase58());
+    expect(events[0].data.recipient.toBase58()).to.equal(
+      recipient.publicKey.toBase58()
+    );
+    expect(events[0].data.mint.toBase58()).to.equal(mint.toBase58());
+    expect(events[0].data.amount.toNumber()).to.equal(amount);
+    expect(events[0].data.fee.toNumber()).to.equal(0);
+    expect(events[0].data.completed).to.be.true;
 
-    // Create escrow transaction
-    // const amount = 500_000_000; // 50 tokens (6 decimals)
-    // const expiry = 60; // 1 minute from now
-    // await program.methods
-    //   .createEscrow(new anchor.BN(amount), new anchor.BN(expiry)) // Pass expiry as a BN
-    //   .accounts({
-    //     escrow: pda,
-    //     depositor: depositor.publicKey,
-    //     depositorTokenAccount,
-    //     escrowTokenAccount,
-    //     recipient: recipient.publicKey,
-    //     mint,
-    //     systemProgram: SystemProgram.programId,
-    //     rent: anchor.web3.SYSVAR_RENT_PUBKEY,
-    //     tokenProgram: TOKEN_2022_PROGRAM_ID,
-    //   })
-    //   .signers([depositor])
-    //   .rpc();
-
-    // const escrow = await program.account.escrow.fetch(pda);
-    // expect(escrow.depositor.toBase58()).to.equal(
-    //   depositor.publicKey.toBase58()
-    // );
-    // expect(escrow.recipient.toBase58()).to.equal(
-    //   recipient.publicKey.toBase58()
-    // );
-    // expect(escrow.amount.toNumber()).to.equal(amount);
-    // expect(escrow.status).to.equal(0); // Pending
+    const recipientTokenBalance =
+      await provider.connection.getTokenAccountBalance(recipientTokenAccount);
+    expect(recipientTokenBalance.value.amount).to.equal(
+      (2 * amount).toString()
+    );
   });
 
-  xit("Funds the escrow", async () => {
-    // Fund the escrow
+  it("Cancels the escrow before the release time", async () => {
+    const [cancelEscrow, cancelVault] = await createEscrow(
+      new anchor.BN(3),
+      60 // 1 minute from now
+    );
+
     await program.methods
-      .fundEscrow()
-      .accounts({
-        escrow: pda,
+      .cancelEscrow()
+      .accountsStrict({
+        escrow: cancelEscrow,
+        authority: depositor.publicKey,
         depositor: depositor.publicKey,
+        mint,
+        vault: cancelVault,
         depositorTokenAccount,
-        escrowTokenAccount,
         tokenProgram: TOKEN_2022_PROGRAM_ID,
       })
       .signers([depositor])
       .rpc();
 
-    const escrow = await program.account.escrow.fetch(pda);
+    expect(await program.account.escrow.fetchNullable(cancelEscrow)).to.be
+      .null;
+
+    const depositorTokenBalance =
+      await provider.connection.getTokenAccountBalance(depositorTokenAccount);
+    expect(depositorTokenBalance.value.amount).to.equal(
+      (2_000_000_000 - 2 * amount).toString()
+    );
+  });
+
+  it("Releases milestones one by one", async () => {
+    const [milestoneEscrow, milestoneVault] = await createEscrow(
+      new anchor.BN(4),
+      0,
+      { cancelPolicy: { never: {} }, milestones: [amount / 2, amount / 2] }
+    );
+    const before = await provider.connection.getTokenAccountBalance(
+      recipientTokenAccount
+    );
+
+    const releaseMilestone = (index: number) =>
+      program.methods
+        .releaseMilestone(index)
+        .accountsStrict({
+          escrow: milestoneEscrow,
+          depositor: depositor.publicKey,
+          recipient: recipient.publicKey,
+          mint,
+          vault: milestoneVault,
+          recipientTokenAccount,
+          config,
+          treasury: findTreasury(mint),
+          tokenProgram: TOKEN_2022_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([depositor])
+        .rpc();
+
+    // The recipient cannot skip the milestones by claiming the whole vault
+    try {
+      await program.methods
+        .claim()
+        .accountsStrict({
+          escrow: milestoneEscrow,
+          recipient: recipient.publicKey,
+          depositor: depositor.publicKey,
+          mint,
+          vault: milestoneVault,
+          recipientTokenAccount,
+          config,
+          treasury: findTreasury(mint),
+          tokenProgram: TOKEN_2022_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([recipient])
+        .rpc();
+      expect.fail("claim should not release a milestone escrow");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.equal("MilestoneEscrow");
+    }
+
+    await releaseMilestone(1);
+    const escrow = await program.account.escrow.fetch(milestoneEscrow);
+    expect(escrow.amountReleased.toNumber()).to.equal(amount / 2);
+    expect(escrow.milestones[1].released).to.equal(true);
     expect(escrow.status).to.equal(0); // Still Pending
 
-    // Check balances
-    const escrowTokenBalance = await provider.connection.getTokenAccountBalance(
-      escrowTokenAccount
+    // Tokens sent to the vault cannot block the last milestone
+    const stray = 1_000;
+    await mintTo(
+      provider.connection,
+      depositor,
+      mint,
+      milestoneVault,
+      depositor.publicKey,
+      stray,
+      undefined,
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+
+    await releaseMilestone(0);
+    expect(await program.account.escrow.fetchNullable(milestoneEscrow)).to.be
+      .null;
+
+    const after = await provider.connection.getTokenAccountBalance(
+      recipientTokenAccount
+    );
+    expect(Number(after.value.amount) - Number(before.value.amount)).to.equal(
+      amount + stray
     );
-    expect(escrowTokenBalance.value.amount).to.equal("500000000");
   });
 
-  xit("Withdraws from the escrow", async () => {
-    // Wait for expiry to simulate an expired escrow
-    await new Promise((resolve) => setTimeout(resolve, 60 * 1000));
+  it("Claims the vested portion of a vesting escrow", async () => {
+    const now = Math.floor(Date.now() / 1000);
+    const vesting = { start: now - 1000, cliff: now - 1000, end: now + 1000 };
+
+    // The depositor may not be able to cancel and take back vested tokens
+    try {
+      await createEscrow(new anchor.BN(5), 0, { vesting });
+      expect.fail("a cancellable vesting escrow should be rejected");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.equal("InvalidVestingSchedule");
+    }
+
+    const [vestingEscrow, vestingVault] = await createEscrow(
+      new anchor.BN(5),
+      0,
+      { cancelPolicy: { never: {} }, vesting }
+    );
 
     await program.methods
-      .withdrawEscrow()
-      .accounts({
-        escrow: pda,
+      .claimVested()
+      .accountsStrict({
+        escrow: vestingEscrow,
         recipient: recipient.publicKey,
+        depositor: depositor.publicKey,
+        mint,
+        vault: vestingVault,
         recipientTokenAccount,
-        escrowTokenAccount,
+        config,
+        treasury: findTreasury(mint),
         tokenProgram: TOKEN_2022_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
       })
       .signers([recipient])
       .rpc();
 
-    const escrow = await program.account.escrow.fetch(pda);
-    expect(escrow.status).to.equal(1); // Completed
+    // Roughly half of the escrow has vested halfway through the schedule
+    const escrow = await program.account.escrow.fetch(vestingEscrow);
+    expect(escrow.amountReleased.toNumber()).to.be.greaterThan(0);
+    expect(escrow.amountReleased.toNumber()).to.be.lessThan(amount);
+    expect(escrow.status).to.equal(0); // Still Pending
+  });
 
-    const recipientTokenBalance =
-      await provider.connection.getTokenAccountBalance(recipientTokenAccount);
-    expect(recipientTokenBalance.value.amount).to.equal("500000000");
+  it("Keeps vested tokens with the recipient after the refund deadline", async () => {
+    const now = Math.floor(Date.now() / 1000);
+    const [vestedEscrow, vestedVault] = await createEscrow(
+      new anchor.BN(21),
+      0,
+      {
+        refundAfter: 1,
+        cancelPolicy: { never: {} },
+        vesting: { start: now - 2000, cliff: now - 2000, end: now - 1000 },
+      }
+    );
+    await new Promise((resolve) => setTimeout(resolve, 2000)); // Pass the refund deadline
+
+    // The depositor cannot take back the fully vested, unclaimed tokens
+    try {
+      await program.methods
+        .cancelEscrow()
+        .accountsStrict({
+          escrow: vestedEscrow,
+          authority: depositor.publicKey,
+          depositor: depositor.publicKey,
+          mint,
+          vault: vestedVault,
+          depositorTokenAccount,
+          tokenProgram: TOKEN_2022_PROGRAM_ID,
+        })
+        .signers([depositor])
+        .rpc();
+      expect.fail("the depositor should not refund a vesting escrow");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.equal("CancelNotAllowed");
+    }
+
+    // Tokens sent to the vault cannot block the final claim
+    const stray = 1_000;
+    await mintTo(
+      provider.connection,
+      depositor,
+      mint,
+      vestedVault,
+      depositor.publicKey,
+      stray,
+      undefined,
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+
+    const before = await provider.connection.getTokenAccountBalance(
+      recipientTokenAccount
+    );
+    await program.methods
+      .claimVested()
+      .accountsStrict({
+        escrow: vestedEscrow,
+        recipient: recipient.publicKey,
+        depositor: depositor.publicKey,
+        mint,
+        vault: vestedVault,
+        recipientTokenAccount,
+        config,
+        treasury: findTreasury(mint),
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([recipient])
+      .rpc();
+
+    const after = await provider.connection.getTokenAccountBalance(
+      recipientTokenAccount
+    );
+    expect(Number(after.value.amount) - Number(before.value.amount)).to.equal(
+      amount + stray
+    );
+    expect(await program.account.escrow.fetchNullable(vestedEscrow)).to.be
+      .null;
+  });
+
+  it("Lets the arbiter split a disputed escrow", async () => {
+    const [disputedEscrow, disputedVault] = await createEscrow(
+      new anchor.BN(6),
+      3600,
+      { cancelPolicy: { never: {} }, arbiter: arbiter.publicKey }
+    );
+
+    const raiseSignature = await program.methods
+      .raiseDispute()
+      .accountsStrict({
+        escrow: disputedEscrow,
+        authority: recipient.publicKey,
+      })
+      .signers([recipient])
+      .rpc({ commitment: "confirmed" });
+
+    const raised = await eventsOf(raiseSignature);
+    expect(raised.map((event) => event.name)).to.deep.equal(["disputeRaised"]);
+    expect(raised[0].data.escrow.toBase58()).to.equal(
+      disputedEscrow.toBase58()
+    );
+    expect(raised[0].data.raisedBy.toBase58()).to.equal(
+      recipient.publicKey.toBase58()
+    );
+
+    const escrow = await program.account.escrow.fetch(disputedEscrow);
+    expect(escrow.status).to.equal(3); // Disputed
+
+    const before = await provider.connection.getTokenAccountBalance(
+      recipientTokenAccount
+    );
+
+    const resolveSignature = await program.methods
+      .resolveDispute(2_500) // 25% to the recipient
+      .accountsStrict({
+        escrow: disputedEscrow,
+        arbiter: arbiter.publicKey,
+        depositor: depositor.publicKey,
+        recipient: recipient.publicKey,
+        mint,
+        vault: disputedVault,
+        depositorTokenAccount,
+        recipientTokenAccount,
+        config,
+        treasury: findTreasury(mint),
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([arbiter])
+      .rpc({ commitment: "confirmed" });
+
+    expect(await program.account.escrow.fetchNullable(disputedEscrow)).to.be
+      .null;
+
+    const resolved = await eventsOf(resolveSignature);
+    expect(resolved.map((event) => event.name)).to.deep.equal([
+      "disputeResolved",
+    ]);
+    expect(resolved[0].data.escrow.toBase58()).to.equal(
+      disputedEscrow.toBase58()
+    );
+    expect(resolved[0].data.arbiter.toBase58()).to.equal(
+      arbiter.publicKey.toBase58()
+    );
+    expect(resolved[0].data.recipientAmount.toNumber()).to.equal(amount / 4);
+    expect(resolved[0].data.depositorAmount.toNumber()).to.equal(
+      amount - amount / 4
+    );
+    expect(resolved[0].data.fee.toNumber()).to.equal(0);
+
+    const after = await provider.connection.getTokenAccountBalance(
+      recipientTokenAccount
+    );
+    expect(Number(after.value.amount) - Number(before.value.amount)).to.equal(
+      amount / 4
+    );
+  });
+
+  it("Requires M-of-N approvals before release", async () => {
+    const approvers = [Keypair.generate(), Keypair.generate(), arbiter];
+    const [approvalEscrow, approvalVault] = await createEscrow(
+      new anchor.BN(7),
+      0,
+      {
+        approvers: approvers.map((a) => a.publicKey),
+        approvalThreshold: 2,
+      }
+    );
+
+    const claim = () =>
+      program.methods
+        .claim()
+        .accountsStrict({
+          escrow: approvalEscrow,
+          recipient: recipient.publicKey,
+          depositor: depositor.publicKey,
+          mint,
+          vault: approvalVault,
+          recipientTokenAccount,
+          config,
+          treasury: findTreasury(mint),
+          tokenProgram: TOKEN_2022_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([recipient])
+        .rpc();
+
+    const approve = (approver: Keypair) =>
+      program.methods
+        .approveRelease()
+        .accountsStrict({
+          escrow: approvalEscrow,
+          approver: approver.publicKey,
+        })
+        .signers([approver])
+        .rpc({ commitment: "confirmed" });
+
+    let escrow = await program.account.escrow.fetch(approvalEscrow);
+    expect(escrow.status).to.equal(4); // AwaitingApprovals
+
+    const approved = await eventsOf(await approve(approvers[0]));
+    expect(approved.map((event) => event.name)).to.deep.equal([
+      "releaseApproved",
+    ]);
+    expect(approved[0].data.escrow.toBase58()).to.equal(
+      approvalEscrow.toBase58()
+    );
+    expect(approved[0].data.approver.toBase58()).to.equal(
+      approvers[0].publicKey.toBase58()
+    );
+    expect(approved[0].data.approvals).to.equal(1);
+    expect(approved[0].data.approvalThreshold).to.equal(2);
+    try {
+      await claim();
+      expect.fail("claim should wait for the approval threshold");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.equal("InvalidStatus");
+    }
+
+    await approve(approvers[2]);
+    escrow = await program.account.escrow.fetch(approvalEscrow);
+    expect(escrow.status).to.equal(0); // Pending
+    expect(escrow.approvals).to.equal(0b101);
+
+    await claim();
+    expect(await program.account.escrow.fetchNullable(approvalEscrow)).to.be
+      .null;
+  });
+
+  it("Swaps tokens atomically through a partially fillable offer", async () => {
+    // Token B is a classic SPL token held by the taker
+    const wantedMint = await createMint(
+      provider.connection,
+      recipient,
+      recipient.publicKey,
+      null,
+      6
+    );
+    const takerWantedTokenAccount = await createAssociatedTokenAccount(
+      provider.connection,
+      recipient,
+      wantedMint,
+      recipient.publicKey
+    );
+    await mintTo(
+      provider.connection,
+      recipient,
+      wantedMint,
+      takerWantedTokenAccount,
+      recipient.publicKey,
+      1_000_000
+    );
+    const makerWantedTokenAccount = getAssociatedTokenAddressSync(
+      wantedMint,
+      depositor.publicKey
+    );
+
+    const [offer, , offerVault] = findEscrow(new anchor.BN(8));
+    await program.methods
+      .makeOffer({
+        escrowId: new anchor.BN(8),
+        amount: new anchor.BN(amount),
+        wantedAmount: new anchor.BN(750_000),
+        taker: recipient.publicKey,
+      })
+      .accountsStrict({
+        escrow: offer,
+        maker: depositor.publicKey,
+        mint,
+        wantedMint,
+        makerTokenAccount: depositorTokenAccount,
+        vault: offerVault,
+        config,
+        mintRule: findMintRule(mint),
+        wantedMintRule: findMintRule(wantedMint),
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([depositor])
+      .rpc();
+
+    const escrow = await program.account.escrow.fetch(offer);
+    expect(escrow.kind).to.equal(1); // Offer
+    expect(escrow.wantedAmount.toNumber()).to.equal(750_000);
+
+    const before = await provider.connection.getTokenAccountBalance(
+      recipientTokenAccount
+    );
+
+    const takeOffer = (fillAmount: number) =>
+      program.methods
+        .takeOffer(new anchor.BN(fillAmount))
+        .accountsStrict({
+          escrow: offer,
+          taker: recipient.publicKey,
+          maker: depositor.publicKey,
+          mint,
+          wantedMint,
+          vault: offerVault,
+          takerWantedTokenAccount,
+          makerWantedTokenAccount,
+          takerTokenAccount: recipientTokenAccount,
+          config,
+          treasury: findTreasury(mint),
+          tokenProgram: TOKEN_2022_PROGRAM_ID,
+          wantedTokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([recipient])
+        .rpc({ commitment: "confirmed" });
+
+    // A third of the wanted tokens buys a third of the offer
+    await takeOffer(250_000);
+    const partial = await program.account.escrow.fetch(offer);
+    expect(partial.amount.toNumber()).to.equal(amount - Math.floor(amount / 3));
+    expect(partial.wantedAmount.toNumber()).to.equal(500_000);
+
+    const filled = await eventsOf(await takeOffer(500_000));
+    expect(await program.account.escrow.fetchNullable(offer)).to.be.null;
+    expect(filled.map((event) => event.name)).to.deep.equal(["offerTaken"]);
+    expect(filled[0].data.escrow.toBase58()).to.equal(offer.toBase58());
+    expect(filled[0].data.taker.toBase58()).to.equal(
+      recipient.publicKey.toBase58()
+    );
+    expect(filled[0].data.amountIn.toNumber()).to.equal(500_000);
+    expect(filled[0].data.amountOut.toNumber()).to.equal(
+      amount - Math.floor(amount / 3)
+    );
+    expect(filled[0].data.fee.toNumber()).to.equal(0);
+    expect(filled[0].data.completed).to.be.true;
+
+    const after = await provider.connection.getTokenAccountBalance(
+      recipientTokenAccount
+    );
+    expect(Number(after.value.amount) - Number(before.value.amount)).to.equal(
+      amount
+    );
+    const makerWanted = await provider.connection.getTokenAccountBalance(
+      makerWantedTokenAccount
+    );
+    expect(makerWanted.value.amount).to.equal("750000");
+  });
+
+  it("Escrows native SOL without wrapping", async () => {
+    const nativeId = new anchor.BN(9);
+    const lamports = anchor.web3.LAMPORTS_PER_SOL / 4;
+    const [nativeEscrow] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("escrow"),
+        depositor.publicKey.toBuffer(),
+        recipient.publicKey.toBuffer(),
+        PublicKey.default.toBuffer(),
+        nativeId.toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+
+    await program.methods
+      .createNativeEscrow({
+        escrowId: nativeId,
+        amount: new anchor.BN(lamports),
+        releaseAfter: { relativeSeconds: [new anchor.BN(0)] },
+        refundAfter: { relativeSeconds: [new anchor.BN(3600)] },
+        cancelPolicy: { beforeRelease: {} },
+      } as any)
+      .accountsStrict({
+        escrow: nativeEscrow,
+        depositor: depositor.publicKey,
+        recipient: recipient.publicKey,
+        config,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([depositor])
+      .rpc();
+
+    const escrow = await program.account.escrow.fetch(nativeEscrow);
+    expect(escrow.kind).to.equal(2); // Native
+    expect(escrow.amount.toNumber()).to.equal(lamports);
+
+    const before = await provider.connection.getBalance(recipient.publicKey);
+    await program.methods
+      .withdrawNativeEscrow()
+      .accountsStrict({
+        escrow: nativeEscrow,
+        depositor: depositor.publicKey,
+        recipient: recipient.publicKey,
+      })
+      .signers([depositor])
+      .rpc();
+
+    expect(await program.account.escrow.fetchNullable(nativeEscrow)).to.be
+      .null;
+    const after = await provider.connection.getBalance(recipient.publicKey);
+    expect(after - before).to.equal(lamports);
+  });
+
+  it("Escrows a single NFT", async () => {
+    const nftMint = await createMint(
+      provider.connection,
+      depositor,
+      depositor.publicKey,
+      null,
+      0, // NFTs are not divisible
+      undefined,
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+    const depositorNftAccount = await createAssociatedTokenAccount(
+      provider.connection,
+      depositor,
+      nftMint,
+      depositor.publicKey,
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+    await mintTo(
+      provider.connection,
+      depositor,
+      nftMint,
+      depositorNftAccount,
+      depositor.publicKey,
+      1,
+      undefined,
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+    const recipientNftAccount = getAssociatedTokenAddressSync(
+      nftMint,
+      recipient.publicKey,
+      false,
+      TOKEN_2022_PROGRAM_ID
+    );
+
+    const [nftEscrow, nftVault] = await createEscrow(new anchor.BN(10), 0, {
+      escrowMint: nftMint,
+      nft: true,
+    });
+
+    await program.methods
+      .claim()
+      .accountsStrict({
+        escrow: nftEscrow,
+        recipient: recipient.publicKey,
+        depositor: depositor.publicKey,
+        mint: nftMint,
+        vault: nftVault,
+        recipientTokenAccount: recipientNftAccount,
+        config,
+        treasury: findTreasury(nftMint),
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([recipient])
+      .rpc();
+
+    const balance = await provider.connection.getTokenAccountBalance(
+      recipientNftAccount
+    );
+    expect(balance.value.amount).to.equal("1");
+  });
+
+  it("Settles escrows of a mint with a Token-2022 transfer fee", async () => {
+    // A mint withholding 1% of every transfer in the receiving account
+    const feeMintKeypair = Keypair.generate();
+    const feeMint = feeMintKeypair.publicKey;
+    const mintLen = getMintLen([ExtensionType.TransferFeeConfig]);
+    await provider.sendAndConfirm(
+      new Transaction().add(
+        SystemProgram.createAccount({
+          fromPubkey: provider.wallet.publicKey,
+          newAccountPubkey: feeMint,
+          space: mintLen,
+          lamports:
+            await provider.connection.getMinimumBalanceForRentExemption(
+              mintLen
+            ),
+          programId: TOKEN_2022_PROGRAM_ID,
+        }),
+        createInitializeTransferFeeConfigInstruction(
+          feeMint,
+          depositor.publicKey,
+          depositor.publicKey,
+          100, // 1%
+          BigInt(amount),
+          TOKEN_2022_PROGRAM_ID
+        ),
+        createInitializeMintInstruction(
+          feeMint,
+          9,
+          depositor.publicKey,
+          null,
+          TOKEN_2022_PROGRAM_ID
+        )
+      ),
+      [feeMintKeypair]
+    );
+    const depositorFeeAccount = await createAssociatedTokenAccount(
+      provider.connection,
+      depositor,
+      feeMint,
+      depositor.publicKey,
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+    await mintTo(
+      provider.connection,
+      depositor,
+      feeMint,
+      depositorFeeAccount,
+      depositor.publicKey,
+      amount,
+      undefined,
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+    const recipientFeeAccount = getAssociatedTokenAddressSync(
+      feeMint,
+      recipient.publicKey,
+      false,
+      TOKEN_2022_PROGRAM_ID
+    );
+
+    // The vault receives the amount minus the fee it withholds
+    const [feeEscrow, feeVault] = await createEscrow(new anchor.BN(22), 0, {
+      escrowMint: feeMint,
+    });
+    const escrow = await program.account.escrow.fetch(feeEscrow);
+    expect(escrow.amount.toNumber()).to.equal((amount * 99) / 100);
+
+    // Closing the vault harvests its withheld fees to the mint first
+    await program.methods
+      .claim()
+      .accountsStrict({
+        escrow: feeEscrow,
+        recipient: recipient.publicKey,
+        depositor: depositor.publicKey,
+        mint: feeMint,
+        vault: feeVault,
+        recipientTokenAccount: recipientFeeAccount,
+        config,
+        treasury: findTreasury(feeMint),
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([recipient])
+      .rpc();
+
+    expect(await provider.connection.getAccountInfo(feeVault)).to.be.null;
+    expect(await program.account.escrow.fetchNullable(feeEscrow)).to.be.null;
+    const received = await provider.connection.getTokenAccountBalance(
+      recipientFeeAccount
+    );
+    expect(Number(received.value.amount)).to.be.greaterThan(0);
+  });
+
+  it("Releases every leg of a basket escrow together", async () => {
+    // A bonus token escrowed alongside the primary mint
+    const bonusMint = await createMint(
+      provider.connection,
+      depositor,
+      depositor.publicKey,
+      null,
+      6,
+      undefined,
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+    const depositorBonusAccount = await createAssociatedTokenAccount(
+      provider.connection,
+      depositor,
+      bonusMint,
+      depositor.publicKey,
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+    await mintTo(
+      provider.connection,
+      depositor,
+      bonusMint,
+      depositorBonusAccount,
+      depositor.publicKey,
+      1_000_000,
+      undefined,
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+    const recipientBonusAccount = await createAssociatedTokenAccount(
+      provider.connection,
+      recipient,
+      bonusMint,
+      recipient.publicKey,
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+
+    // Every leg is checked against its own mint rule
+    const setBonusPaused = (paused: boolean) =>
+      program.methods
+        .setMintPaused(paused)
+        .accountsStrict({
+          config,
+          authority: arbiter.publicKey, // Set as guardian in the config
+          mint: bonusMint,
+          mintRule: findMintRule(bonusMint),
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([arbiter])
+        .rpc();
+    await setBonusPaused(true);
+    try {
+      await createEscrow(new anchor.BN(11), 0, {
+        basket: [{ mint: bonusMint, amount: 1_000_000 }],
+      });
+      expect.fail("a paused basket leg should be rejected");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.equal("MintPaused");
+    }
+    await setBonusPaused(false);
+
+    const [basketEscrow, basketVault] = await createEscrow(
+      new anchor.BN(11),
+      0,
+      { basket: [{ mint: bonusMint, amount: 1_000_000 }] }
+    );
+    const bonusVault = getAssociatedTokenAddressSync(
+      bonusMint,
+      basketEscrow,
+      true,
+      TOKEN_2022_PROGRAM_ID
+    );
+
+    const escrow = await program.account.escrow.fetch(basketEscrow);
+    expect(escrow.basket[0].mint.toBase58()).to.equal(bonusMint.toBase58());
+    expect(escrow.basket[0].amount.toNumber()).to.equal(1_000_000);
+
+    // Fees on the bonus leg are collected into its own treasury
+    await program.methods
+      .initializeTreasury()
+      .accountsStrict({
+        config,
+        payer: provider.wallet.publicKey,
+        mint: bonusMint,
+        treasury: findTreasury(bonusMint),
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    await program.methods
+      .withdrawEscrow()
+      .accountsStrict({
+        escrow: basketEscrow,
+        depositor: depositor.publicKey,
+        recipient: recipient.publicKey,
+        mint,
+        vault: basketVault,
+        recipientTokenAccount,
+        config,
+        treasury: findTreasury(mint),
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .remainingAccounts([
+        { pubkey: bonusMint, isSigner: false, isWritable: true },
+        { pubkey: bonusVault, isSigner: false, isWritable: true },
+        { pubkey: recipientBonusAccount, isSigner: false, isWritable: true },
+        {
+          pubkey: findTreasury(bonusMint),
+          isSigner: false,
+          isWritable: true,
+        },
+      ])
+      .signers([depositor])
+      .rpc();
+
+    expect(await provider.connection.getAccountInfo(bonusVault)).to.be.null;
+    const bonus = await provider.connection.getTokenAccountBalance(
+      recipientBonusAccount
+    );
+    expect(bonus.value.amount).to.equal("1000000");
+  });
+
+  it("Splits the release between recipients by basis points", async () => {
+    const partner = arbiter; // Any funded wallet can take a share
+    const partnerTokenAccount = await createAssociatedTokenAccount(
+      provider.connection,
+      partner,
+      mint,
+      partner.publicKey,
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+    const [splitEscrow, splitVault] = await createEscrow(new anchor.BN(12), 0, {
+      splits: [
+        { recipient: recipient.publicKey, bps: 7_000 },
+        { recipient: partner.publicKey, bps: 3_000 },
+      ],
+    });
+
+    const before = await provider.connection.getTokenAccountBalance(
+      recipientTokenAccount
+    );
+    await program.methods
+      .claim()
+      .accountsStrict({
+        escrow: splitEscrow,
+        recipient: recipient.publicKey,
+        depositor: depositor.publicKey,
+        mint,
+        vault: splitVault,
+        recipientTokenAccount,
+        config,
+        treasury: findTreasury(mint),
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .remainingAccounts([
+        { pubkey: partnerTokenAccount, isSigner: false, isWritable: true },
+      ])
+      .signers([recipient])
+      .rpc();
+
+    const after = await provider.connection.getTokenAccountBalance(
+      recipientTokenAccount
+    );
+    expect(Number(after.value.amount) - Number(before.value.amount)).to.equal(
+      (amount * 7) / 10
+    );
+    const partnerBalance = await provider.connection.getTokenAccountBalance(
+      partnerTokenAccount
+    );
+    expect(Number(partnerBalance.value.amount)).to.equal((amount * 3) / 10);
+  });
+
+  it("Emits an event for every state transition", async () => {
+    const [eventEscrow, eventVault, createSignature] = await createEscrow(
+      new anchor.BN(13),
+      60
+    );
+
+    const created = await eventsOf(createSignature);
+    expect(created.map((event) => event.name)).to.deep.equal([
+      "escrowCreated",
+      "escrowFunded",
+    ]);
+    expect(created[0].data.escrow.toBase58()).to.equal(eventEscrow.toBase58());
+    expect(created[0].data.depositor.toBase58()).to.equal(
+      depositor.publicKey.toBase58()
+    );
+    expect(created[0].data.recipient.toBase58()).to.equal(
+      recipient.publicKey.toBase58()
+    );
+    expect(created[0].data.mint.toBase58()).to.equal(mint.toBase58());
+    expect(created[0].data.kind).to.equal(0); // Standard
+    expect(created[0].data.amount.toNumber()).to.equal(amount);
+    expect(
+      created[0].data.refundAfter.sub(created[0].data.releaseAfter).toNumber()
+    ).to.equal(3600);
+    expect(created[1].data.escrow.toBase58()).to.equal(eventEscrow.toBase58());
+    expect(created[1].data.depositor.toBase58()).to.equal(
+      depositor.publicKey.toBase58()
+    );
+    expect(created[1].data.mint.toBase58()).to.equal(mint.toBase58());
+    expect(created[1].data.amount.toNumber()).to.equal(amount);
+
+    const signature = await program.methods
+      .cancelEscrow()
+      .accountsStrict({
+        escrow: eventEscrow,
+        authority: depositor.publicKey,
+        depositor: depositor.publicKey,
+        mint,
+        vault: eventVault,
+        depositorTokenAccount,
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+      })
+      .signers([depositor])
+      .rpc({ commitment: "confirmed" });
+
+    const events = await eventsOf(signature);
+    expect(events.map((event) => event.name)).to.deep.equal([
+      "escrowCancelled",
+    ]);
+    expect(events[0].data.escrow.toBase58()).to.equal(eventEscrow.toBase58());
+    expect(events[0].data.depositor.toBase58()).to.equal(
+      depositor.publicKey.toBase58()
+    );
+    expect(events[0].data.mint.toBase58()).to.equal(mint.toBase58());
+    expect(events[0].data.amount.toNumber()).to.equal(amount);
+    expect(events[0].data.cancelledBy.toBase58()).to.equal(
+      depositor.publicKey.toBase58()
+    );
+  });
+
+  it("Hands the config admin role over in two steps", async () => {
+    const admin = provider.wallet.publicKey;
+    const newAdmin = arbiter;
+
+    await program.methods
+      .transferAdmin(newAdmin.publicKey)
+      .accountsStrict({ config, admin })
+      .rpc();
+    let settings = await program.account.config.fetch(config);
+    expect(settings.admin.toBase58()).to.equal(admin.toBase58());
+    expect(settings.pendingAdmin.toBase58()).to.equal(
+      newAdmin.publicKey.toBase58()
+    );
+
+    await program.methods
+      .acceptAdmin()
+      .accountsStrict({ config, newAdmin: newAdmin.publicKey })
+      .signers([newAdmin])
+      .rpc();
+    settings = await program.account.config.fetch(config);
+    expect(settings.admin.toBase58()).to.equal(newAdmin.publicKey.toBase58());
+    expect(settings.pendingAdmin).to.be.null;
+
+    // Hand the role back so the provider wallet stays in charge
+    await program.methods
+      .transferAdmin(admin)
+      .accountsStrict({ config, admin: newAdmin.publicKey })
+      .signers([newAdmin])
+      .rpc();
+    await program.methods
+      .acceptAdmin()
+      .accountsStrict({ config, newAdmin: admin })
+      .rpc();
+  });
+
+  it("Collects protocol fees into the treasury on release", async () => {
+    const admin = provider.wallet.publicKey;
+    const treasury = findTreasury(mint);
+    const setFee = (feeBps: number) =>
+      program.methods
+        .updateConfig({
+          feeBps,
+          mintPolicy: { allowAll: {} },
+          paused: false,
+          guardian: arbiter.publicKey,
+          maxDuration: new anchor.BN(maxDuration),
+        } as any)
+        .accountsStrict({ config, admin })
+        .rpc();
+
+    // The fee rate is capped
+    try {
+      await setFee(1_001);
+      expect.fail("fees above 10% should be rejected");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.equal("FeeTooHigh");
+    }
+
+    await setFee(100); // 1%
+    const [feeEscrow, feeVault] = await createEscrow(new anchor.BN(14), 0);
+
+    // Raising the fee afterwards does not apply to the funded escrow
+    await setFee(1_000);
+
+    const before = await provider.connection.getTokenAccountBalance(
+      recipientTokenAccount
+    );
+    const claimSignature = await program.methods
+      .claim()
+      .accountsStrict({
+        escrow: feeEscrow,
+        recipient: recipient.publicKey,
+        depositor: depositor.publicKey,
+        mint,
+        vault: feeVault,
+        recipientTokenAccount,
+        config,
+        treasury,
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([recipient])
+      .rpc({ commitment: "confirmed" });
+    await setFee(0);
+
+    const released = await eventsOf(claimSignature);
+    expect(released.map((event) => event.name)).to.deep.equal([
+      "escrowReleased",
+    ]);
+    expect(released[0].data.escrow.toBase58()).to.equal(feeEscrow.toBase58());
+    expect(released[0].data.recipient.toBase58()).to.equal(
+      recipient.publicKey.toBase58()
+    );
+    expect(released[0].data.mint.toBase58()).to.equal(mint.toBase58());
+    expect(released[0].data.amount.toNumber()).to.equal((amount * 99) / 100);
+    expect(released[0].data.fee.toNumber()).to.equal(amount / 100);
+    expect(released[0].data.completed).to.be.true;
+
+    const after = await provider.connection.getTokenAccountBalance(
+      recipientTokenAccount
+    );
+    expect(Number(after.value.amount) - Number(before.value.amount)).to.equal(
+      (amount * 99) / 100
+    );
+    const collected = await provider.connection.getTokenAccountBalance(
+      treasury
+    );
+    expect(Number(collected.value.amount)).to.equal(amount / 100);
+
+    await program.methods
+      .withdrawFees(new anchor.BN(amount / 100))
+      .accountsStrict({
+        config,
+        admin,
+        mint,
+        treasury,
+        destination: depositorTokenAccount,
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+      })
+      .rpc();
+    const emptied = await provider.connection.getTokenAccountBalance(treasury);
+    expect(emptied.value.amount).to.equal("0");
+  });
+
+  it("Never lets the flat minimum fee take the whole release", async () => {
+    const admin = provider.wallet.publicKey;
+    const treasury = findTreasury(mint);
+    const setFee = async (feeBps: number, feeMin: number) =>
+      program.methods
+        .updateConfig({
+          feeBps,
+          mintPolicy: { allowAll: {} },
+          paused: false,
+          guardian: arbiter.publicKey,
+          maxDuration: new anchor.BN(maxDuration),
+        } as any)
+        .accountsStrict({ config, admin })
+        .postInstructions([
+          // The minimum fee is set per mint, in its base units
+          await program.methods
+            .setMintFeeMin(new anchor.BN(feeMin))
+            .accountsStrict({
+              config,
+              authority: admin,
+              mint,
+              mintRule: findMintRule(mint),
+              systemProgram: SystemProgram.programId,
+            })
+            .instruction(),
+        ])
+        .rpc();
+
+    // A minimum above the escrowed amount falls back to the 1% rate
+    await setFee(100, amount * 2);
+    const [smallEscrow, smallVault] = await createEscrow(
+      new anchor.BN(19),
+      0
+    );
+
+    const before = await provider.connection.getTokenAccountBalance(
+      recipientTokenAccount
+    );
+    const collectedBefore = await provider.connection.getTokenAccountBalance(
+      treasury
+    );
+    await program.methods
+      .claim()
+      .accountsStrict({
+        escrow: smallEscrow,
+        recipient: recipient.publicKey,
+        depositor: depositor.publicKey,
+        mint,
+        vault: smallVault,
+        recipientTokenAccount,
+        config,
+        treasury,
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([recipient])
+      .rpc();
+    await setFee(0, 0);
+
+    const after = await provider.connection.getTokenAccountBalance(
+      recipientTokenAccount
+    );
+    expect(Number(after.value.amount) - Number(before.value.amount)).to.equal(
+      (amount * 99) / 100
+    );
+    const collectedAfter = await provider.connection.getTokenAccountBalance(
+      treasury
+    );
+    expect(
+      Number(collectedAfter.value.amount) - Number(collectedBefore.value.amount)
+    ).to.equal(amount / 100);
+  });
+
+  it("Blocks new escrows while paused but still allows refunds", async () => {
+    const guardian = arbiter; // Set as guardian in the config
+    const setPaused = (paused: boolean) =>
+      program.methods
+        .setPaused(paused)
+        .accountsStrict({ config, authority: guardian.publicKey })
+        .signers([guardian])
+        .rpc();
+
+    const [openEscrow, openVault] = await createEscrow(new anchor.BN(15), 60);
+    await setPaused(true);
+
+    try {
+      await createEscrow(new anchor.BN(16), 60);
+      expect.fail("create_escrow should fail while paused");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.equal("ProgramPaused");
+    }
+
+    // Existing escrows can still be cancelled
+    await program.methods
+      .cancelEscrow()
+      .accountsStrict({
+        escrow: openEscrow,
+        authority: depositor.publicKey,
+        depositor: depositor.publicKey,
+        mint,
+        vault: openVault,
+        depositorTokenAccount,
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+      })
+      .signers([depositor])
+      .rpc();
+    await setPaused(false);
+
+    // A paused mint blocks only escrows of that mint
+    await program.methods
+      .setMintPaused(true)
+      .accountsStrict({
+        config,
+        authority: guardian.publicKey,
+        mint,
+        mintRule: findMintRule(mint),
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([guardian])
+      .rpc();
+    try {
+      await createEscrow(new anchor.BN(16), 60);
+      expect.fail("create_escrow should fail while the mint is paused");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.equal("MintPaused");
+    }
+    await program.methods
+      .setMintPaused(false)
+      .accountsStrict({
+        config,
+        authority: guardian.publicKey,
+        mint,
+        mintRule: findMintRule(mint),
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([guardian])
+      .rpc();
+  });
+
+  it("Enforces the mint allowlist at escrow creation", async () => {
+    const admin = provider.wallet.publicKey;
+    const setPolicy = (mintPolicy: object) =>
+      program.methods
+        .updateConfig({
+          feeBps: 0,
+          mintPolicy,
+          paused: false,
+          guardian: arbiter.publicKey,
+          maxDuration: new anchor.BN(maxDuration),
+        } as any)
+        .accountsStrict({ config, admin })
+        .rpc();
+    const setListing = (listing: object) =>
+      program.methods
+        .setMintListing(listing as any)
+        .accountsStrict({
+          config,
+          authority: admin,
+          mint,
+          mintRule: findMintRule(mint),
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+
+    await setPolicy({ allowlistOnly: {} });
+    try {
+      await createEscrow(new anchor.BN(17), 60);
+      expect.fail("unlisted mints should be rejected");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.equal("MintNotAllowed");
+    }
+
+    await setListing({ allowed: {} });
+
+    // Basket legs must be listed too
+    const unlistedMint = await createMint(
+      provider.connection,
+      depositor,
+      depositor.publicKey,
+      null,
+      9,
+      undefined,
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+    try {
+      await createEscrow(new anchor.BN(17), 60, {
+        basket: [{ mint: unlistedMint, amount: 1_000_000 }],
+      });
+      expect.fail("unlisted basket legs should be rejected");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.equal("MintNotAllowed");
+    }
+
+    const [allowedEscrow] = await createEscrow(new anchor.BN(17), 60);
+    expect(await program.account.escrow.fetchNullable(allowedEscrow)).to.not.be
+      .null;
+
+    await setPolicy({ denyListed: {} });
+    await setListing({ denied: {} });
+    try {
+      await createEscrow(new anchor.BN(18), 60);
+      expect.fail("denylisted mints should be rejected");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.equal("MintNotAllowed");
+    }
+
+    await setListing({ unlisted: {} });
+    await setPolicy({ allowAll: {} });
+  });
+
+  it("Enforces the configured maximum escrow duration", async () => {
+    const admin = provider.wallet.publicKey;
+    const setMaxDuration = (seconds: number) =>
+      program.methods
+        .updateConfig({
+          feeBps: 0,
+          mintPolicy: { allowAll: {} },
+          paused: false,
+          guardian: arbiter.publicKey,
+          maxDuration: new anchor.BN(seconds),
+        } as any)
+        .accountsStrict({ config, admin })
+        .rpc();
+
+    await setMaxDuration(3600); // 1 hour
+    try {
+      await createEscrow(new anchor.BN(20), 60, { refundAfter: 7200 });
+      expect.fail("escrows locked beyond the maximum should be rejected");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.equal("DurationTooLong");
+    }
+
+    const [shortEscrow] = await createEscrow(new anchor.BN(20), 60, {
+      refundAfter: 1800,
+    });
+    expect(await program.account.escrow.fetchNullable(shortEscrow)).to.not.be
+      .null;
+    await setMaxDuration(maxDuration);
   });
 });